@@ -0,0 +1,198 @@
+use std::path::Path;
+
+use async_openai::types::{MessageContentTextAnnotations, TextData};
+use log::error;
+use regex::{Captures, Regex};
+
+use crate::{database::blob::Minio, openai::OpenAI};
+
+/// How an annotation of the assistant's text is shown in Discord.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rendered {
+    /// A citation, shown as a numbered footnote with the name of the cited file
+    Footnote { marker: String, file_name: String },
+    /// A file the assistant created, the sandbox path is replaced by `link`. Without a link
+    /// the file couldn't be uploaded, and only its name is shown.
+    File {
+        marker: String,
+        file_name: String,
+        link: Option<String>,
+    },
+}
+
+/// Replaces markdown links to `marker` by their label and `file_name`, and the remaining
+/// occurrences of `marker` by `file_name`.
+fn unlink(text: &str, marker: &str, file_name: &str) -> String {
+    let pattern = format!(r"\[([^\]]*)\]\({}\)", regex::escape(marker));
+    let text = match Regex::new(&pattern) {
+        Ok(links) => links
+            .replace_all(text, |captures: &Captures| {
+                format!("{} ({}, download unavailable)", &captures[1], file_name)
+            })
+            .into_owned(),
+        Err(_) => text.to_string(),
+    };
+    text.replace(marker, file_name)
+}
+
+/// Rewrites the text: citation markers like `【4:0†source】` become footnote numbers with
+/// the cited files listed at the end, sandbox paths become download links, or plain file
+/// names when the file couldn't be uploaded.
+pub fn render(value: &str, annotations: &[Rendered]) -> String {
+    let mut text = value.to_string();
+    let mut footnotes: Vec<&str> = vec![];
+    for annotation in annotations {
+        match annotation {
+            Rendered::Footnote { marker, file_name } => {
+                let number = match footnotes.iter().position(|name| name == file_name) {
+                    Some(index) => index + 1,
+                    None => {
+                        footnotes.push(file_name);
+                        footnotes.len()
+                    }
+                };
+                text = text.replace(marker.as_str(), &format!("[{}]", number));
+            }
+            Rendered::File {
+                marker,
+                link: Some(link),
+                ..
+            } => text = text.replace(marker.as_str(), link),
+            Rendered::File {
+                marker,
+                file_name,
+                link: None,
+            } => text = unlink(&text, marker, file_name),
+        }
+    }
+
+    if !footnotes.is_empty() {
+        let footnotes = footnotes
+            .iter()
+            .enumerate()
+            .map(|(index, file_name)| format!("[{}] {}", index + 1, file_name))
+            .collect::<Vec<String>>()
+            .join("\n");
+        text = format!("{}\n\n{}", text.trim_end(), footnotes);
+    }
+    text
+}
+
+/// The name of the file, falling back to its id when it can't be looked up.
+async fn file_name(openai: &OpenAI, file_id: &str) -> String {
+    openai.file_name(file_id).await.unwrap_or_else(|err| {
+        error!("Failed to get the name of file {}: {}", file_id, err);
+        file_id.to_string()
+    })
+}
+
+/// Downloads a file the assistant created and uploads it to Minio, returning its url.
+async fn upload(openai: &OpenAI, file_id: &str, file_name: &str) -> Result<String, String> {
+    let dir = format!("./files/{}", file_id);
+    let path = openai
+        .save_file(file_id, file_name, &dir)
+        .await
+        .map_err(|err| format!("Failed to download {}: {}", file_name, err))?;
+
+    let url = Minio::new()
+        .upload_file(&path.display().to_string(), file_name)
+        .await;
+    if let Err(err) = tokio::fs::remove_dir_all(&dir).await {
+        error!("Failed to remove {}: {}", dir, err);
+    }
+    url
+}
+
+async fn resolve(openai: &OpenAI, annotation: &MessageContentTextAnnotations) -> Rendered {
+    match annotation {
+        MessageContentTextAnnotations::FileCitation(citation) => Rendered::Footnote {
+            marker: citation.text.clone(),
+            file_name: file_name(openai, &citation.file_citation.file_id).await,
+        },
+        MessageContentTextAnnotations::FilePath(file) => {
+            let file_id = &file.file_path.file_id;
+            let name = match Path::new(&file.text).file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => file_name(openai, file_id).await,
+            };
+            let link = upload(openai, file_id, &name)
+                .await
+                .map_err(|err| error!("{}", err))
+                .ok();
+            Rendered::File {
+                marker: file.text.clone(),
+                file_name: name,
+                link,
+            }
+        }
+    }
+}
+
+/// Renders the annotations of a text from the assistant for Discord.
+pub async fn render_text(openai: &OpenAI, text: &TextData) -> String {
+    let mut annotations = vec![];
+    for annotation in &text.annotations {
+        annotations.push(resolve(openai, annotation).await);
+    }
+    render(&text.value, &annotations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn footnote(marker: &str, file_name: &str) -> Rendered {
+        Rendered::Footnote {
+            marker: marker.to_string(),
+            file_name: file_name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_citations_become_footnotes() {
+        let text = "Bees dance【4:0†source】 and sing【4:1†source】, ants march【4:2†source】.";
+        let annotations = vec![
+            footnote("【4:0†source】", "bees.pdf"),
+            footnote("【4:1†source】", "bees.pdf"),
+            footnote("【4:2†source】", "ants.pdf"),
+        ];
+        assert_eq!(
+            render(text, &annotations),
+            "Bees dance[1] and sing[1], ants march[2].\n\n[1] bees.pdf\n[2] ants.pdf"
+        );
+    }
+
+    #[test]
+    fn test_file_paths_become_links() {
+        let text = "Here is [the chart](sandbox:/mnt/data/chart.csv)";
+        let annotations = vec![Rendered::File {
+            marker: "sandbox:/mnt/data/chart.csv".to_string(),
+            file_name: "chart.csv".to_string(),
+            link: Some("https://files.example.com/files/1/chart.csv".to_string()),
+        }];
+        assert_eq!(
+            render(text, &annotations),
+            "Here is [the chart](https://files.example.com/files/1/chart.csv)"
+        );
+    }
+
+    #[test]
+    fn test_files_without_link_are_plain_text() {
+        let text =
+            "Here is [the chart](sandbox:/mnt/data/chart.csv), see sandbox:/mnt/data/chart.csv";
+        let annotations = vec![Rendered::File {
+            marker: "sandbox:/mnt/data/chart.csv".to_string(),
+            file_name: "chart.csv".to_string(),
+            link: None,
+        }];
+        assert_eq!(
+            render(text, &annotations),
+            "Here is the chart (chart.csv, download unavailable), see chart.csv"
+        );
+    }
+
+    #[test]
+    fn test_text_without_annotations() {
+        assert_eq!(render("Plain text", &[]), "Plain text");
+    }
+}
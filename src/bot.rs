@@ -1,45 +1,69 @@
 use async_openai::types::{AssistantObject, MessageContent};
-use log::{debug, info};
+use log::{debug, error, info};
 use regex::Regex;
 use serenity::all::{Command, Interaction};
 use serenity::async_trait;
-use serenity::builder::{CreateAttachment, CreateMessage, CreateWebhook, ExecuteWebhook};
+use serenity::builder::{
+    CreateAttachment, CreateEmbed, CreateEmbedAuthor, CreateMessage, CreateWebhook, EditMessage,
+    EditWebhookMessage, ExecuteWebhook,
+};
 use serenity::framework::standard::{
     macros::{command, group},
     CommandResult,
 };
+use serenity::model::id::{ChannelId, MessageId};
 use serenity::model::webhook::Webhook;
-use serenity::model::{channel::Message, gateway::Ready};
+use serenity::model::{
+    channel::{Channel, Message},
+    gateway::Ready,
+};
 use serenity::prelude::*;
 use songbird::SerenityInit;
 use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-
-use crate::database::channels::{get_channel, set_channel, ChannelConfiguration};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use crate::annotations::render_text;
+use crate::config::env_or;
+use crate::conversation::{converse, is_echo, Conversations};
+use crate::database::channels::{ChannelConfiguration, ChannelStore};
+use crate::database::tasks::TaskStore;
 use crate::database::users::{User, UserStore};
-use crate::openai::{OpenAI, ThreadStore};
+use crate::openai::{AssistantCache, OpenAI, ThreadStore};
 use crate::thread::OpenAIThread;
+use crate::tools::{available_tools, ToolRegistry};
+use crate::triggers::{default_triggers, triggered, webhook_id, IncomingMessage, Trigger};
 
-struct Handler;
+#[derive(Default)]
+struct Handler {
+    reminders_started: AtomicBool,
+}
 
-async fn webhook_say(
+/// Reads the avatar url an assistant stores in its metadata.
+pub fn assistant_avatar(assistant: &AssistantObject) -> Option<&str> {
+    assistant
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get("avatar"))
+        .and_then(|avatar| avatar.as_str())
+}
+
+pub async fn webhook_say(
     ctx: &Context,
     webhook: &str,
+    thread_id: Option<ChannelId>,
     message: &str,
     files: Vec<&str>,
     avatar: Option<&str>,
     username: Option<&str>,
-) {
-    let webhook = Webhook::from_url(&ctx.http, webhook)
-        .await
-        .expect("Failed to get webhook");
+) -> Result<(), serenity::Error> {
+    let webhook = Webhook::from_url(&ctx.http, webhook).await?;
 
     let mut attachments = vec![];
     for file in files {
-        let attachment = CreateAttachment::path(file)
-            .await
-            .expect("Failed to create attachment");
-        attachments.push(attachment);
+        attachments.push(CreateAttachment::path(file).await?);
     }
 
     let hook = ExecuteWebhook::new()
@@ -58,10 +82,289 @@ async fn webhook_say(
         hook
     };
 
-    webhook
-        .execute(&ctx.http, false, hook)
-        .await
-        .expect("Failed to execute webhook");
+    let hook = if let Some(thread_id) = thread_id {
+        hook.in_thread(thread_id)
+    } else {
+        hook
+    };
+
+    webhook.execute(&ctx.http, false, hook).await?;
+    Ok(())
+}
+
+/// Posts a message as an assistant: through the channel webhook when there is one, or as
+/// a bot message with an embed showing the assistant, like in DMs. `thread_id` is the
+/// Discord thread to post in when the webhook belongs to its parent channel.
+pub async fn persona_say(
+    ctx: &Context,
+    channel_id: ChannelId,
+    webhook: Option<&str>,
+    thread_id: Option<ChannelId>,
+    message: &str,
+    files: Vec<&str>,
+    avatar: Option<&str>,
+    username: Option<&str>,
+) -> Result<(), serenity::Error> {
+    if let Some(webhook) = webhook {
+        return webhook_say(ctx, webhook, thread_id, message, files, avatar, username).await;
+    }
+
+    let mut attachments = vec![];
+    for file in files {
+        attachments.push(CreateAttachment::path(file).await?);
+    }
+
+    // Without text, like for images, there is nothing to put in the embed
+    let mut create = CreateMessage::new().add_files(attachments);
+    if !message.is_empty() {
+        create = create.embed(persona_embed(message, avatar, username));
+    }
+    channel_id.send_message(&ctx.http, create).await?;
+    Ok(())
+}
+
+/// Embed showing the assistant, for channels without a webhook.
+pub(crate) fn persona_embed(
+    message: &str,
+    avatar: Option<&str>,
+    username: Option<&str>,
+) -> CreateEmbed {
+    let mut embed = CreateEmbed::new();
+    if !message.is_empty() {
+        embed = embed.description(message);
+    }
+    if let Some(username) = username {
+        let mut author = CreateEmbedAuthor::new(username);
+        if let Some(avatar) = avatar {
+            author = author.icon_url(avatar);
+        }
+        embed = embed.author(author);
+    }
+    embed
+}
+
+/// Downloads an image the assistant created, like a code interpreter chart, and posts it
+/// as an attachment under the assistant's persona.
+async fn post_image(
+    ctx: &Context,
+    channel_id: ChannelId,
+    webhook: Option<&str>,
+    thread_id: Option<ChannelId>,
+    file_id: &str,
+    avatar: Option<&str>,
+    username: Option<&str>,
+) -> Result<(), String> {
+    let image = {
+        let data = ctx.data.read().await;
+        let openai = data.get::<OpenAI>().expect("Expected OpenAI in TypeMap");
+        openai
+            .save_file(file_id, &format!("{}.png", file_id), "./images")
+            .await
+            .map_err(|err| format!("Failed to download image {}: {}", file_id, err))?
+    };
+
+    let image_path = image.display().to_string();
+    let result = persona_say(
+        ctx,
+        channel_id,
+        webhook,
+        thread_id,
+        "",
+        vec![&image_path],
+        avatar,
+        username,
+    )
+    .await
+    .map_err(|err| format!("Failed to send image: {}", err));
+
+    if let Err(err) = tokio::fs::remove_file(&image).await {
+        error!("Failed to remove image {}: {}", image_path, err);
+    }
+    result
+}
+
+/// Discord allows 5 edits per 5 seconds in a channel, streamed replies stay below that.
+const EDIT_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// An assistant message that is posted while the answer is still being written and edited
+/// as it grows. Text that doesn't fit in one Discord message continues in new messages.
+struct LiveReply<'a> {
+    ctx: &'a Context,
+    channel_id: ChannelId,
+    webhook_url: Option<&'a str>,
+    webhook: Option<Webhook>,
+    thread_id: Option<ChannelId>,
+    avatar: Option<&'a str>,
+    username: Option<&'a str>,
+    text: String,
+    sent: Vec<(MessageId, String)>,
+    last_sync: Option<Instant>,
+}
+
+impl<'a> LiveReply<'a> {
+    fn new(
+        ctx: &'a Context,
+        channel_id: ChannelId,
+        webhook_url: Option<&'a str>,
+        thread_id: Option<ChannelId>,
+        avatar: Option<&'a str>,
+        username: Option<&'a str>,
+    ) -> Self {
+        LiveReply {
+            ctx,
+            channel_id,
+            webhook_url,
+            webhook: None,
+            thread_id,
+            avatar,
+            username,
+            text: String::new(),
+            sent: vec![],
+            last_sync: None,
+        }
+    }
+
+    /// Appends the deltas as they arrive, updating Discord at most every `EDIT_INTERVAL`.
+    async fn stream(&mut self, mut deltas: UnboundedReceiver<String>) {
+        while let Some(delta) = deltas.recv().await {
+            self.text.push_str(&delta);
+            if self
+                .last_sync
+                .map_or(true, |last_sync| last_sync.elapsed() >= EDIT_INTERVAL)
+            {
+                self.sync().await;
+            }
+        }
+    }
+
+    /// Replaces whatever was streamed with the final text.
+    async fn finish(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.sync().await;
+    }
+
+    /// Removes whatever was streamed, for when the answer has to be posted differently.
+    async fn discard(&mut self) {
+        for (id, _) in std::mem::take(&mut self.sent) {
+            if let Err(err) = self.delete(id).await {
+                error!("Failed to delete message: {}", err);
+            }
+        }
+        self.text.clear();
+    }
+
+    async fn sync(&mut self) {
+        self.last_sync = Some(Instant::now());
+        let chunks = self
+            .text
+            .split_to_vector(2000)
+            .into_iter()
+            .filter(|chunk| !chunk.trim().is_empty())
+            .collect::<Vec<String>>();
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            let sent = self
+                .sent
+                .get(index)
+                .map(|(id, content)| (*id, content == chunk));
+            let result = match sent {
+                Some((_, true)) => continue,
+                Some((id, false)) => self
+                    .edit(id, chunk)
+                    .await
+                    .map(|_| self.sent[index].1 = chunk.clone()),
+                None => self
+                    .post(chunk)
+                    .await
+                    .map(|id| self.sent.push((id, chunk.clone()))),
+            };
+            if let Err(err) = result {
+                error!("Failed to send message: {}", err);
+                return;
+            }
+        }
+
+        for (id, _) in self.sent.split_off(chunks.len().min(self.sent.len())) {
+            if let Err(err) = self.delete(id).await {
+                error!("Failed to delete message: {}", err);
+            }
+        }
+    }
+
+    async fn post(&mut self, content: &str) -> Result<MessageId, serenity::Error> {
+        if let (None, Some(url)) = (&self.webhook, self.webhook_url) {
+            self.webhook = Some(Webhook::from_url(&self.ctx.http, url).await?);
+        }
+
+        let message = match &self.webhook {
+            Some(webhook) => {
+                let mut hook = ExecuteWebhook::new().content(content);
+                if let Some(avatar) = self.avatar {
+                    hook = hook.avatar_url(avatar);
+                }
+                if let Some(username) = self.username {
+                    hook = hook.username(username);
+                }
+                if let Some(thread_id) = self.thread_id {
+                    hook = hook.in_thread(thread_id);
+                }
+                webhook
+                    .execute(&self.ctx.http, true, hook)
+                    .await?
+                    .ok_or(serenity::Error::Other("Webhook didn't return the message"))?
+            }
+            None => {
+                self.channel_id
+                    .send_message(
+                        &self.ctx.http,
+                        CreateMessage::new().embed(persona_embed(
+                            content,
+                            self.avatar,
+                            self.username,
+                        )),
+                    )
+                    .await?
+            }
+        };
+        Ok(message.id)
+    }
+
+    async fn edit(&self, id: MessageId, content: &str) -> Result<(), serenity::Error> {
+        match &self.webhook {
+            Some(webhook) => {
+                let mut edit = EditWebhookMessage::new().content(content);
+                if let Some(thread_id) = self.thread_id {
+                    edit = edit.in_thread(thread_id);
+                }
+                webhook.edit_message(&self.ctx.http, id, edit).await?;
+            }
+            None => {
+                self.channel_id
+                    .edit_message(
+                        &self.ctx.http,
+                        id,
+                        EditMessage::new().embed(persona_embed(
+                            content,
+                            self.avatar,
+                            self.username,
+                        )),
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, id: MessageId) -> Result<(), serenity::Error> {
+        match &self.webhook {
+            Some(webhook) => {
+                webhook
+                    .delete_message(&self.ctx.http, self.thread_id, id)
+                    .await
+            }
+            None => self.channel_id.delete_message(&self.ctx.http, id).await,
+        }
+    }
 }
 
 pub async fn register_user(ctx: &Context, msg: &Message) {
@@ -80,157 +383,314 @@ pub async fn register_user(ctx: &Context, msg: &Message) {
         .expect("Failed to register user");
 }
 
-async fn multi_agent_response(
+/// Runs the assistant on the thread and posts its answer, returning the text it posted.
+pub(crate) async fn assistant_response(
     msg: &Message,
     ctx: &Context,
     thread: &OpenAIThread,
-    webhook: &str,
-    assistants: &Vec<AssistantObject>,
-) {
-    for assistant in assistants {
-        if msg.content.to_lowercase().contains(
-            &assistant
-                .name
-                .clone()
-                .unwrap_or("assistant".to_string())
-                .to_lowercase(),
-        ) {
-            if msg.author.bot {
-                debug!("Ignoring message from bot");
-                continue;
-            }
-            let typing = msg.channel_id.start_typing(&ctx.http);
-            let result = thread.run(&ctx, &assistant.id).await;
-            match result {
-                Ok(result) => {
-                    for content in result {
-                        match content {
-                            MessageContent::Text(text) => {
-                                for message_content in text.text.value.split_to_vector(2000) {
-                                    let avatar = if let Some(avatar) = &assistant.metadata {
-                                        avatar.get("avatar").map(|v| v.as_str()).flatten()
-                                    } else {
-                                        None
-                                    };
-                                    webhook_say(
-                                        &ctx,
-                                        &webhook,
-                                        &message_content,
-                                        vec![],
-                                        avatar,
-                                        assistant.name.as_deref(),
-                                    )
-                                    .await;
-                                }
-                            }
-                            MessageContent::ImageFile(_image) => {
-                                webhook_say(
-                                    &ctx,
-                                    &webhook,
-                                    "IMAGE: Image format not supported yet",
-                                    Vec::new(),
-                                    None,
-                                    None,
-                                )
-                                .await;
-                            }
-                        }
-                    }
-                }
-                Err(err) => {
-                    webhook_say(
-                        &ctx,
-                        &webhook,
-                        format!("error: {}", err).as_str(),
-                        vec![],
-                        None,
-                        None,
-                    )
-                    .await
-                }
-            }
-            typing.stop();
-        }
-    }
-}
+    channel_config: &ChannelConfiguration,
+    assistant: &AssistantObject,
+) -> String {
+    let webhook = channel_config.webhook.as_deref();
+    let webhook_thread = channel_config
+        .webhook_thread(msg.channel_id.get())
+        .map(ChannelId::new);
+    let typing = msg.channel_id.start_typing(&ctx.http);
+    let avatar = assistant_avatar(assistant);
+    let new_reply = || {
+        LiveReply::new(
+            ctx,
+            msg.channel_id,
+            webhook,
+            webhook_thread,
+            avatar,
+            assistant.name.as_deref(),
+        )
+    };
 
-async fn default_response(msg: &Message, ctx: &Context, thread: &OpenAIThread) {
-    if msg.content.to_lowercase().contains("lovelace") && msg.author.bot == false {
-        let typing = msg.channel_id.start_typing(&ctx.http);
-        let result = thread.run(&ctx, "asst_P66RVsW92Izpwky1qWDAZMO8").await;
-        if let Err(err_msg) = result {
-            msg.channel_id
-                .say(&ctx.http, format!("Error: {:?}", err_msg))
-                .await
-                .expect("Failed to send message");
-        } else {
-            for content in result.expect("Failed to get result") {
+    // The answer streams into the first text reply. Once the run is done, the other texts
+    // and images of the message follow in their original order.
+    let mut streamed = new_reply();
+    let (deltas, receiver) = unbounded_channel();
+    let (result, _) = tokio::join!(
+        thread.run(&ctx, msg.channel_id, &assistant.id, Some(deltas)),
+        streamed.stream(receiver)
+    );
+
+    let mut reply = vec![];
+    match result {
+        Ok(result) => {
+            // Images that come before the first text would end up after the streamed text,
+            // so the streamed text is removed and posted again in the original order.
+            let text_first = matches!(result.first(), Some(MessageContent::Text(_)));
+            if !text_first {
+                streamed.discard().await;
+            }
+            let mut streamed = Some(streamed).filter(|_| text_first);
+            for content in result {
                 match content {
                     MessageContent::Text(text) => {
-                        for message_content in text.text.value.split_to_vector(2000) {
-                            let message = CreateMessage::new().content(message_content);
-                            msg.channel_id
-                                .send_message(&ctx.http, message)
-                                .await
-                                .expect("Failed to send message");
-                        }
+                        let value = {
+                            let data = ctx.data.read().await;
+                            let openai = data.get::<OpenAI>().expect("Expected OpenAI in TypeMap");
+                            render_text(openai, &text.text).await
+                        };
+                        streamed
+                            .take()
+                            .unwrap_or_else(new_reply)
+                            .finish(&value)
+                            .await;
+                        reply.push(value);
                     }
-                    MessageContent::ImageFile(_image) => {
-                        msg.channel_id
-                            .say(&ctx.http, "IMAGE: Image format not supported yet")
-                            .await
-                            .expect("Failed to send image");
+                    MessageContent::ImageFile(image) => {
+                        if let Err(err) = post_image(
+                            ctx,
+                            msg.channel_id,
+                            webhook,
+                            webhook_thread,
+                            &image.image_file.file_id,
+                            avatar,
+                            assistant.name.as_deref(),
+                        )
+                        .await
+                        {
+                            error!("Failed to post image: {}", err);
+                        }
                     }
                 }
             }
         }
-        typing.stop();
+        Err(err) => {
+            // The error replaces whatever was streamed before the run failed
+            streamed.discard().await;
+            if let Err(err) = persona_say(
+                &ctx,
+                msg.channel_id,
+                webhook,
+                webhook_thread,
+                format!("error: {}", err).as_str(),
+                vec![],
+                avatar,
+                assistant.name.as_deref(),
+            )
+            .await
+            {
+                error!("Failed to send message: {}", err);
+            }
+        }
     }
+    typing.stop();
+    reply.join("\n")
 }
 
-async fn get_or_create_channel_config(
+async fn multi_agent_response(
     msg: &Message,
     ctx: &Context,
-    store: &mut ThreadStore,
-) -> ChannelConfiguration {
-    let channel_config = get_channel(msg.channel_id.get()).expect("Failed to get channel");
-    if channel_config.is_none() {
-        debug!("Channel not configured");
-        let webhook = msg
+    thread: &OpenAIThread,
+    channel_config: &ChannelConfiguration,
+    assistants: &Vec<AssistantObject>,
+    generation: u64,
+) {
+    let active = assistants
+        .iter()
+        .filter(|assistant| channel_config.is_active(&assistant.id))
+        .collect::<Vec<&AssistantObject>>();
+
+    let bot_id = ctx.cache.current_user().id;
+    let webhook = channel_config.webhook.as_deref();
+    let own_webhook = webhook.and_then(webhook_id);
+    let replied_to_assistant = msg
+        .referenced_message
+        .as_ref()
+        .and_then(|replied| match webhook {
+            Some(_) if replied.webhook_id.map(|id| id.get()) == own_webhook => {
+                Some(replied.author.name.as_str())
+            }
+            // Without a webhook assistants post embeds carrying their name
+            None if replied.author.id == bot_id => replied
+                .embeds
+                .first()
+                .and_then(|embed| embed.author.as_ref())
+                .map(|author| author.name.as_str()),
+            _ => None,
+        });
+    let incoming = IncomingMessage {
+        content: &msg.content,
+        mentions_bot: msg.mentions_user_id(bot_id),
+        replied_to_assistant,
+    };
+
+    let responding = triggered(
+        &channel_config.triggers,
+        &incoming,
+        &active,
+        channel_config.default_assistant.as_deref(),
+    );
+    if responding.is_empty() && msg.is_private() && channel_config.default_assistant.is_none() {
+        if let Err(err) = msg
             .channel_id
-            .create_webhook(&ctx.http, CreateWebhook::new("assistants"))
+            .say(
+                &ctx.http,
+                "Pick the assistant to talk to with `/assistant default`",
+            )
             .await
-            .expect("Failed to create webhook");
+        {
+            error!("Failed to send message: {}", err);
+        }
+        return;
+    }
+
+    converse(
+        msg,
+        ctx,
+        thread,
+        channel_config,
+        assistants,
+        responding,
+        generation,
+    )
+    .await;
+}
+
+/// Creates the webhook assistants post through in a guild channel.
+async fn create_channel_webhook(ctx: &Context, channel_id: ChannelId) -> Result<String, String> {
+    let webhook = channel_id
+        .create_webhook(&ctx.http, CreateWebhook::new("assistants"))
+        .await
+        .map_err(|err| format!("Failed to create webhook: {}", err))?;
+    webhook
+        .url()
+        .map_err(|err| format!("Failed to get webhook url: {}", err))
+}
+
+fn channel_defaults(webhook: Option<String>) -> ChannelConfiguration {
+    ChannelConfiguration {
+        active_assistants: vec![],
+        thread: String::new(),
+        webhook,
+        default_assistant: None,
+        triggers: default_triggers(),
+        parent_channel: None,
+        max_rounds: 0,
+        moderator: None,
+    }
+}
+
+/// Makes sure the OpenAI thread of `config` is in the store.
+fn load_thread(config: &ChannelConfiguration, store: &mut ThreadStore) {
+    if store.get(&config.thread).is_none() {
+        store.add_thread(OpenAIThread::from_existing(&config.thread));
+    }
+}
+
+/// Gives `config` its own OpenAI thread and stores it as the configuration of `channel_id`.
+/// When another message configured the channel in the meantime, that configuration wins.
+async fn save_new_config(
+    ctx: &Context,
+    channel_id: ChannelId,
+    mut config: ChannelConfiguration,
+    channels: &ChannelStore,
+    store: &Mutex<ThreadStore>,
+) -> Result<ChannelConfiguration, String> {
+    let thread = OpenAIThread::new().await;
+    config.thread = thread.id().to_owned();
+
+    let existing = {
+        let mut store = store.lock().await;
+        match channels.get(channel_id.get())? {
+            Some(existing) => {
+                load_thread(&existing, &mut store);
+                existing
+            }
+            None => {
+                channels.set(channel_id.get(), &config)?;
+                store.add_thread(thread);
+                return Ok(config);
+            }
+        }
+    };
 
-        let thread = OpenAIThread::new().await;
+    debug!("Channel {} was configured concurrently", channel_id);
+    // Threads post through the webhook of their parent, only a channel's own one is extra
+    if let (None, Some(webhook)) = (config.parent_channel, &config.webhook) {
+        if existing.webhook.as_ref() != Some(webhook) {
+            let deleted = match Webhook::from_url(&ctx.http, webhook).await {
+                Ok(webhook) => webhook.delete(&ctx.http).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = deleted {
+                error!("Failed to delete extra webhook: {}", err);
+            }
+        }
+    }
+    Ok(existing)
+}
 
-        let config = ChannelConfiguration {
-            active_assistants: vec![],
-            thread: thread.id().to_owned(),
-            webhook: webhook.url().expect("Failed to get webhook url"),
+/// The configuration of the channel, set up with a webhook and an OpenAI thread when the
+/// channel is first used. The store is shared by all channels, so it is only locked to
+/// look up and insert threads, not during the Discord and OpenAI requests in between.
+pub async fn get_or_create_channel_config(
+    channel_id: ChannelId,
+    ctx: &Context,
+    channels: &ChannelStore,
+    store: &Mutex<ThreadStore>,
+) -> Result<ChannelConfiguration, String> {
+    let channel_config = channels.get(channel_id.get())?;
+    if channel_config.is_none() {
+        debug!("Channel not configured");
+        let config = match channel_id.to_channel(&ctx).await {
+            // DMs have no webhooks, assistants answer there as bot messages with an embed
+            Ok(Channel::Private(_)) => {
+                let mut config = channel_defaults(None);
+                config.triggers.push(Trigger::Always);
+                config
+            }
+            // Discord threads and forum posts can't have webhooks, they post through the
+            // parent's webhook and start out with the parent's settings
+            Ok(Channel::Guild(channel)) if channel.thread_metadata.is_some() => {
+                let parent_id = channel.parent_id.expect("Expected a parent for thread");
+                let parent = match channels.get(parent_id.get())? {
+                    Some(parent) => parent,
+                    None => {
+                        let webhook = create_channel_webhook(ctx, parent_id).await?;
+                        let config = channel_defaults(Some(webhook));
+                        save_new_config(ctx, parent_id, config, channels, store).await?
+                    }
+                };
+                ChannelConfiguration {
+                    parent_channel: Some(parent_id.get()),
+                    ..parent
+                }
+            }
+            _ => channel_defaults(Some(create_channel_webhook(ctx, channel_id).await?)),
         };
-        set_channel(msg.channel_id.get(), &config).expect("Failed to set channel");
 
-        store.add_thread(thread);
-        config
+        save_new_config(ctx, channel_id, config, channels, store).await
     } else {
         let channel_config = channel_config.unwrap();
-        if store.get(&channel_config.thread).is_none() {
-            let thread = OpenAIThread::from_existing(&channel_config.thread);
-            store.add_thread(thread);
-        }
+        load_thread(&channel_config, &mut *store.lock().await);
 
         debug!(
             "Channel configuration found with thread: {}",
             channel_config.thread.clone()
         );
-        channel_config
+        Ok(channel_config)
     }
 }
 
 #[async_trait]
 impl EventHandler for Handler {
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::Autocomplete(interaction) = &interaction {
+            if matches!(
+                interaction.data.name.as_str(),
+                "assistant" | "triggers" | "conversation"
+            ) {
+                crate::commands::assistant::autocomplete(&ctx, interaction).await;
+            };
+            return;
+        }
+
         if let Interaction::Command(command) = interaction {
             debug!("Received command {:#?}", command.data.name);
 
@@ -257,38 +717,104 @@ impl EventHandler for Handler {
             if command.data.name.as_str() == "reset" {
                 crate::commands::reset::run(&ctx, &command).await;
             };
+
+            if command.data.name.as_str() == "triggers" {
+                crate::commands::triggers::run(&ctx, &command).await;
+            };
+
+            if command.data.name.as_str() == "thread" {
+                crate::commands::thread::run(&ctx, &command).await;
+            };
+
+            if command.data.name.as_str() == "conversation" {
+                crate::commands::conversation::run(&ctx, &command).await;
+            };
         }
     }
 
     async fn message(&self, ctx: Context, msg: Message) {
         debug!("Received message: {:?}", msg.content);
         let read_lock = ctx.data.read().await;
-        let mut store = read_lock
-            .get::<ThreadStore>()
-            .expect("Expected ThreadStore in TypeMap")
-            .lock()
-            .await;
-
-        let channel_config = get_or_create_channel_config(&msg, &ctx, &mut store).await;
-        debug!("Channel config: {:?}", channel_config);
 
+        // Human messages interrupt the conversation between assistants in the channel
+        let generation = (!msg.author.bot).then(|| {
+            read_lock
+                .get::<Conversations>()
+                .expect("Expected Conversations in TypeMap")
+                .begin(msg.channel_id.get())
+        });
+
+        // The store is shared by all channels, only hold it for the lookup so channels run
+        // in parallel. Runs on the same thread queue up in the thread itself.
+        let store = read_lock
+            .get::<ThreadStore>()
+            .expect("Expected ThreadStore in TypeMap");
+        let channels = read_lock
+            .get::<ChannelStore>()
+            .expect("Expected ChannelStore in TypeMap");
+        let channel_config =
+            match get_or_create_channel_config(msg.channel_id, &ctx, channels, store).await {
+                Ok(channel_config) => channel_config,
+                Err(err) => {
+                    error!("Failed to configure channel {}: {}", msg.channel_id, err);
+                    return;
+                }
+            };
         let thread = store
+            .lock()
+            .await
             .get(&channel_config.thread)
             .expect("Failed to get thread");
+        debug!("Channel config: {:?}", channel_config);
+
+        if is_echo(&msg, &channel_config, ctx.cache.current_user().id) {
+            debug!("Ignoring message posted by the assistants");
+            return;
+        }
 
         debug!("Adding message to thread");
-        thread
+        // A message that couldn't be added stays queued, the run adds it or fails
+        if let Err(err) = thread
             .add_message(format!("{}: {}", msg.author.id.get(), msg.content.clone()))
-            .await;
+            .await
+        {
+            error!("Failed to add message to thread: {}", err);
+        }
 
         let openai = read_lock
             .get::<OpenAI>()
             .expect("Expected OpenAI in TypeMap");
-        let assistants = openai.assistants().await;
+        let assistants = read_lock
+            .get::<AssistantCache>()
+            .expect("Expected AssistantCache in TypeMap")
+            .assistants(openai)
+            .await;
+        let assistants = match assistants {
+            Ok(assistants) => assistants,
+            Err(err) => {
+                error!("Failed to list assistants: {}", err);
+                return;
+            }
+        };
 
         debug!("processing message");
         register_user(&ctx, &msg).await;
-        multi_agent_response(&msg, &ctx, &thread, &channel_config.webhook, &assistants).await
+        let generation = match generation {
+            Some(generation) => generation,
+            None => {
+                debug!("Ignoring message from bot");
+                return;
+            }
+        };
+        multi_agent_response(
+            &msg,
+            &ctx,
+            &thread,
+            &channel_config,
+            &assistants,
+            generation,
+        )
+        .await
     }
 
     async fn ready(&self, ctx: Context, ready: Ready) {
@@ -296,6 +822,10 @@ impl EventHandler for Handler {
         if env::var("DELETE_COMMANDS").is_ok_and(|v| v == "true") {
             recreate_commands(&ctx).await;
         };
+
+        if !self.reminders_started.swap(true, Ordering::SeqCst) {
+            tokio::spawn(crate::reminders::run(ctx.clone()));
+        }
     }
 }
 
@@ -333,6 +863,18 @@ async fn recreate_commands(ctx: &Context) {
     Command::create_global_command(&ctx.http, crate::commands::reset::register())
         .await
         .expect("Failed to create global command");
+
+    Command::create_global_command(&ctx.http, crate::commands::triggers::register())
+        .await
+        .expect("Failed to create global command");
+
+    Command::create_global_command(&ctx.http, crate::commands::thread::register())
+        .await
+        .expect("Failed to create global command");
+
+    Command::create_global_command(&ctx.http, crate::commands::conversation::register())
+        .await
+        .expect("Failed to create global command");
 }
 
 #[group]
@@ -382,6 +924,10 @@ impl TypeMapKey for OpenAI {
     type Value = OpenAI;
 }
 
+impl TypeMapKey for AssistantCache {
+    type Value = Arc<AssistantCache>;
+}
+
 impl TypeMapKey for ThreadStore {
     type Value = Arc<Mutex<ThreadStore>>;
 }
@@ -390,6 +936,28 @@ impl TypeMapKey for UserStore {
     type Value = Arc<RwLock<UserStore>>;
 }
 
+impl TypeMapKey for TaskStore {
+    type Value = TaskStore;
+}
+
+impl TypeMapKey for ChannelStore {
+    type Value = ChannelStore;
+}
+
+impl TypeMapKey for ToolRegistry {
+    type Value = Arc<ToolRegistry>;
+}
+
+impl TypeMapKey for Conversations {
+    type Value = Arc<Conversations>;
+}
+
+/// How long the assistant list is cached, configurable through
+/// `ASSISTANT_CACHE_TTL_SECONDS`.
+fn assistant_cache_ttl() -> Duration {
+    Duration::from_secs(env_or("ASSISTANT_CACHE_TTL_SECONDS", 300))
+}
+
 pub struct Bot;
 
 impl Bot {
@@ -399,7 +967,7 @@ impl Bot {
 
     pub async fn start(&self, token: &str) {
         let mut client = Client::builder(token, GatewayIntents::all())
-            .event_handler(Handler)
+            .event_handler(Handler::default())
             .register_songbird()
             .await
             .expect("Error creating client");
@@ -409,8 +977,13 @@ impl Bot {
             let openai = OpenAI::new();
 
             data.insert::<OpenAI>(openai);
+            data.insert::<AssistantCache>(Arc::new(AssistantCache::new(assistant_cache_ttl())));
             data.insert::<ThreadStore>(Arc::new(Mutex::new(ThreadStore::new())));
             data.insert::<UserStore>(Arc::new(RwLock::new(UserStore::new())));
+            data.insert::<TaskStore>(TaskStore::new());
+            data.insert::<ChannelStore>(ChannelStore::new());
+            data.insert::<ToolRegistry>(Arc::new(available_tools()));
+            data.insert::<Conversations>(Arc::new(Conversations::default()));
         }
 
         if let Err(why) = client.start().await {
@@ -1,21 +1,35 @@
 use std::time::Duration;
 
-use async_openai::types::AssistantTools;
-use log::debug;
+use async_openai::types::{AssistantObject, AssistantTools};
+use log::{debug, error};
 use serenity::{
     all::{
-        CommandDataOptionValue, CommandInteraction, CommandOptionType, ComponentInteractionDataKind,
+        ButtonStyle, CommandDataOption, CommandDataOptionValue, CommandInteraction,
+        CommandOptionType, ComponentInteractionDataKind, InputTextStyle,
     },
     builder::{
-        CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponse,
-        CreateInteractionResponseMessage, CreateMessage, CreateSelectMenu, CreateSelectMenuKind,
-        CreateSelectMenuOption,
+        CreateActionRow, CreateAttachment, CreateAutocompleteResponse, CreateButton, CreateCommand,
+        CreateCommandOption, CreateEmbed, CreateInputText, CreateInteractionResponse,
+        CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateMessage,
+        CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, EditInteractionResponse,
     },
     client::Context,
+    model::id::ChannelId,
     utils::CreateQuickModal,
 };
 
-use crate::{bot::SplitToVector, openai::OpenAI, tools::available_tools};
+use crate::{
+    bot::{assistant_avatar, get_or_create_channel_config, SplitToVector},
+    database::channels::{ChannelConfiguration, ChannelStore},
+    openai::{AssistantCache, OpenAI, ThreadStore},
+    tools::ToolRegistry,
+};
+
+const DEFAULT_MODEL: &str = "gpt-4-1106-preview";
+
+/// How long modals wait for the user. Interaction tokens last 15 minutes, telling the user
+/// the modal was cancelled has to fit in.
+const MODAL_TIMEOUT: Duration = Duration::from_secs(60 * 14);
 
 fn clip_instructions(instructions: String) -> String {
     let parts = instructions.split_to_vector(500);
@@ -28,163 +42,378 @@ fn clip_instructions(instructions: String) -> String {
     }
 }
 
+fn assistant_embed(assistant: &AssistantObject) -> CreateEmbed {
+    let embed = CreateEmbed::new()
+        .field(
+            "Name",
+            assistant.name.clone().unwrap_or("None".to_string()),
+            true,
+        )
+        .field("Id", assistant.id.as_str(), true)
+        .field("Model", assistant.model.clone(), true)
+        .field(
+            "Instructions",
+            clip_instructions(assistant.instructions.clone().unwrap_or("None".to_string())),
+            false,
+        );
+
+    let embed = match &assistant.description {
+        Some(description) => embed.description(description),
+        None => embed,
+    };
+    match assistant_avatar(assistant) {
+        Some(avatar) => embed.thumbnail(avatar),
+        None => embed,
+    }
+}
+
+/// Assistants running on `model` and having `tool` enabled, when given.
+fn filter_assistants<'a>(
+    assistants: &'a [AssistantObject],
+    model: Option<&str>,
+    tool: Option<&str>,
+) -> Vec<&'a AssistantObject> {
+    assistants
+        .iter()
+        .filter(|assistant| model.map_or(true, |model| assistant.model.eq_ignore_ascii_case(model)))
+        .filter(|assistant| {
+            tool.map_or(true, |tool| {
+                tool_names(assistant)
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(tool))
+            })
+        })
+        .collect()
+}
+
+fn list_page(
+    pages: &[&[&AssistantObject]],
+    page: usize,
+    total: usize,
+) -> CreateInteractionResponseMessage {
+    let embeds = pages[page]
+        .iter()
+        .map(|assistant| assistant_embed(assistant))
+        .collect::<Vec<CreateEmbed>>();
+
+    CreateInteractionResponseMessage::new()
+        .content(format!(
+            "Page {}/{} ({} assistants)",
+            page + 1,
+            pages.len(),
+            total
+        ))
+        .embeds(embeds)
+        .components(vec![CreateActionRow::Buttons(vec![
+            CreateButton::new("assistant_list_previous")
+                .label("Previous")
+                .style(ButtonStyle::Secondary)
+                .disabled(page == 0),
+            CreateButton::new("assistant_list_next")
+                .label("Next")
+                .style(ButtonStyle::Secondary)
+                .disabled(page + 1 >= pages.len()),
+        ])])
+}
+
+/// Reads a string option of the subcommand `option`.
+pub(crate) fn subcommand_option<'a>(option: &'a CommandDataOption, name: &str) -> Option<&'a str> {
+    match &option.value {
+        CommandDataOptionValue::SubCommand(options) => options
+            .iter()
+            .find(|option| option.name == name)
+            .and_then(|option| option.value.as_str()),
+        _ => None,
+    }
+}
+
+/// Finds an assistant by id or (case insensitive) name.
+pub(crate) fn find_assistant<'a>(
+    assistants: &'a [AssistantObject],
+    name_or_id: &str,
+) -> Option<&'a AssistantObject> {
+    let name_or_id = name_or_id.trim();
+    assistants
+        .iter()
+        .find(|assistant| assistant.id == name_or_id)
+        .or_else(|| {
+            assistants.iter().find(|assistant| {
+                assistant
+                    .name
+                    .as_ref()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(name_or_id))
+            })
+        })
+}
+
+/// Assistants per page of `/assistant list`, Discord allows at most 10 embeds per message
+/// and 6000 characters over all of them.
+const LIST_PAGE_SIZE: usize = 5;
+
+/// Discord shows at most 25 autocomplete suggestions.
+const MAX_AUTOCOMPLETE_CHOICES: usize = 25;
+
+/// Longest value Discord accepts in a modal text input.
+const MAX_INPUT_LENGTH: usize = 4000;
+
+/// Longest description Discord accepts in an embed.
+const EMBED_DESCRIPTION_LIMIT: usize = 4096;
+
+pub(crate) async fn respond(
+    ctx: &Context,
+    command: &CommandInteraction,
+    content: impl Into<String>,
+) {
+    let message = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new().content(content),
+    );
+    command
+        .create_response(&ctx.http, message)
+        .await
+        .expect("Failed to create interaction response");
+}
+
+/// Follows up on `command` once it was answered with a modal.
+async fn follow_up(ctx: &Context, command: &CommandInteraction, content: impl Into<String>) {
+    if let Err(err) = command
+        .create_followup(
+            &ctx.http,
+            CreateInteractionResponseFollowup::new().content(content),
+        )
+        .await
+    {
+        error!("Failed to follow up on interaction: {}", err);
+    }
+}
+
+/// Replaces the deferred response of `command`, which can't be responded to again.
+async fn edit_content(ctx: &Context, command: &CommandInteraction, content: impl Into<String>) {
+    if let Err(err) = command
+        .edit_response(&ctx.http, EditInteractionResponse::new().content(content))
+        .await
+    {
+        error!("Failed to edit interaction response: {}", err);
+    }
+}
+
+fn tool_names(assistant: &AssistantObject) -> Vec<String> {
+    assistant
+        .tools
+        .iter()
+        .map(|tool| match tool {
+            AssistantTools::Code(_) => "code_interpreter".to_string(),
+            AssistantTools::Retrieval(_) => "retrieval".to_string(),
+            AssistantTools::Function(function) => function.function.name.clone(),
+        })
+        .collect()
+}
+
+fn inspect_message(assistant: &AssistantObject) -> CreateInteractionResponseMessage {
+    let instructions = assistant.instructions.clone().unwrap_or_default();
+    let tools = tool_names(assistant);
+    let metadata = serde_json::to_string_pretty(&assistant.metadata.clone().unwrap_or_default())
+        .unwrap_or_default();
+
+    let embed = CreateEmbed::new()
+        .title(assistant.name.clone().unwrap_or("None".to_string()))
+        .field("Id", assistant.id.as_str(), true)
+        .field("Model", assistant.model.clone(), true)
+        .field(
+            "Description",
+            assistant.description.clone().unwrap_or("None".to_string()),
+            false,
+        )
+        .field(
+            "Tools",
+            if tools.is_empty() {
+                "None".to_string()
+            } else {
+                tools.join(", ")
+            },
+            false,
+        )
+        .field(
+            "Metadata",
+            format!("```json\n{}\n```", clip_instructions(metadata)),
+            false,
+        );
+    let embed = match assistant_avatar(assistant) {
+        Some(avatar) => embed.thumbnail(avatar),
+        None => embed,
+    };
+
+    // Instructions that don't fit in the description are attached
+    if instructions.chars().count() <= EMBED_DESCRIPTION_LIMIT {
+        CreateInteractionResponseMessage::new().embed(embed.description(instructions))
+    } else {
+        CreateInteractionResponseMessage::new()
+            .embed(embed.description("Instructions are attached"))
+            .add_file(CreateAttachment::bytes(
+                instructions.into_bytes(),
+                "instructions.txt",
+            ))
+    }
+}
+
+pub(crate) async fn channel_configuration(
+    ctx: &Context,
+    channel_id: ChannelId,
+) -> Result<ChannelConfiguration, String> {
+    let data = ctx.data.read().await;
+    let store = data
+        .get::<ThreadStore>()
+        .expect("Expected ThreadStore in TypeMap");
+    let channels = data
+        .get::<ChannelStore>()
+        .expect("Expected ChannelStore in TypeMap");
+    get_or_create_channel_config(channel_id, ctx, channels, store).await
+}
+
+/// Stores the configuration of the channel.
+pub(crate) async fn save_channel_configuration(
+    ctx: &Context,
+    channel_id: ChannelId,
+    config: &ChannelConfiguration,
+) -> Result<(), String> {
+    let data = ctx.data.read().await;
+    data.get::<ChannelStore>()
+        .expect("Expected ChannelStore in TypeMap")
+        .set(channel_id.get(), config)
+}
+
 pub async fn run(ctx: &Context, command: &CommandInteraction) {
     let data = ctx.data.read().await;
     let openai = data.get::<OpenAI>().expect("Expected OpenAI in TypeMap");
+    let cache = data
+        .get::<AssistantCache>()
+        .expect("Expected AssistantCache in TypeMap");
 
     let options = &command.data.options;
     for option in options.iter() {
         match option.name.as_str() {
             "image" => {
-                let m = command
-                    .quick_modal(
-                        &ctx,
-                        CreateQuickModal::new("Set assistant profile image")
-                            .short_field("Id")
-                            .short_field("Url"),
-                    )
-                    .await
-                    .expect("Failed to send message");
-
-                match m {
-                    Some(res) => {
-                        let (id, url) = (&res.inputs[0], &res.inputs[1]);
-                        debug!("Id: {}, Url: {}", id, url);
-                        let assistants = openai.assistants().await;
-                        let assistant = assistants
-                            .iter()
-                            .find(|assistant| assistant.id == id.clone());
-
-                        match assistant {
-                            Some(assistant) => {
-                                debug!("trying to aquire read lock");
-                                let data_read = ctx.data.read().await;
-                                let openai = data_read
-                                    .get::<OpenAI>()
-                                    .expect("Expected OpenAI in ShareMap");
-                                debug!("aquired read lock");
-                                openai
-                                    .set_assistant_image(assistant, url)
-                                    .await
-                                    .expect("Failed to set assistant image");
-
-                                let message = CreateInteractionResponse::Message(
-                                    CreateInteractionResponseMessage::new().content(format!(
-                                        "Successfully changed {} pfp to {}",
-                                        assistant.name.clone().unwrap_or_default(),
-                                        url
-                                    )),
-                                );
-                                res.interaction
-                                    .create_response(&ctx.http, message)
-                                    .await
-                                    .expect("Failed to create interaction response");
-                            }
-                            None => {
-                                let message = CreateInteractionResponse::Message(
-                                    CreateInteractionResponseMessage::new()
-                                        .content("Invalid assistant id"),
-                                );
-                                command
-                                    .create_response(&ctx.http, message)
-                                    .await
-                                    .expect("Failed to create interaction response");
-                                return;
-                            }
-                        }
+                let name_or_id = subcommand_option(option, "assistant").unwrap_or_default();
+                let url = subcommand_option(option, "url").unwrap_or_default();
+                debug!("Assistant: {}, Url: {}", name_or_id, url);
+                let assistants = match cache.assistants(openai).await {
+                    Ok(assistants) => assistants,
+                    Err(err) => {
+                        respond(ctx, command, format!("Failed to list assistants: {}", err)).await;
+                        return;
                     }
+                };
+                let assistant = match find_assistant(&assistants, name_or_id) {
+                    Some(assistant) => assistant,
                     None => {
-                        let message = CreateInteractionResponse::Message(
-                            CreateInteractionResponseMessage::new()
-                                .content("Assistant creation cancelled"),
-                        );
-                        command
-                            .create_response(&ctx.http, message)
-                            .await
-                            .expect("Failed to create interaction response");
+                        respond(ctx, command, format!("Unknown assistant: {}", name_or_id)).await;
                         return;
                     }
-                }
+                };
+
+                let content = match openai.set_assistant_image(assistant, url).await {
+                    Ok(()) => format!(
+                        "Successfully changed {} pfp to {}",
+                        assistant.name.clone().unwrap_or_default(),
+                        url
+                    ),
+                    Err(err) => format!("Failed to set assistant image: {}", err),
+                };
+                cache.invalidate().await;
+                respond(ctx, command, content).await;
+                return;
             }
             "list" => {
-                let query = [("limit", "10")];
-                let assistants = openai
-                    .client
-                    .assistants()
-                    .list(&query)
-                    .await
-                    .expect("Failed to list assistants");
-                let embeds = assistants
-                    .data
-                    .iter()
-                    .map(|assistant| {
-                        CreateEmbed::new()
-                            .field(
-                                "Name",
-                                assistant.name.clone().unwrap_or("None".to_string()),
-                                true,
-                            )
-                            .field("Id", assistant.id.as_str(), true)
-                            .field("Model", assistant.model.clone(), true)
-                            .field(
-                                "Instructions",
-                                clip_instructions(
-                                    assistant.instructions.clone().unwrap_or("None".to_string()),
-                                ),
-                                false,
-                            )
-                    })
-                    .collect::<Vec<CreateEmbed>>();
-                let message = CreateInteractionResponse::Message(
-                    CreateInteractionResponseMessage::new().add_embeds(embeds),
-                );
+                let model = subcommand_option(option, "model");
+                let tool = subcommand_option(option, "tool");
+                let assistants = match cache.assistants(openai).await {
+                    Ok(assistants) => assistants,
+                    Err(err) => {
+                        respond(ctx, command, format!("Failed to list assistants: {}", err)).await;
+                        return;
+                    }
+                };
+                let assistants = filter_assistants(&assistants, model, tool);
+                if assistants.is_empty() {
+                    respond(ctx, command, "No assistants found").await;
+                    return;
+                }
+
+                let pages = assistants.chunks(LIST_PAGE_SIZE).collect::<Vec<_>>();
+                let mut page = 0;
                 command
-                    .create_response(&ctx.http, message)
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::Message(list_page(
+                            &pages,
+                            page,
+                            assistants.len(),
+                        )),
+                    )
                     .await
                     .expect("Failed to create interaction response");
+                if pages.len() == 1 {
+                    return;
+                }
+
+                let m = command
+                    .get_response(&ctx.http)
+                    .await
+                    .expect("Failed to get interaction response");
+                while let Some(interaction) = m
+                    .await_component_interaction(&ctx.shard)
+                    .timeout(Duration::from_secs(60 * 5))
+                    .await
+                {
+                    page = match interaction.data.custom_id.as_str() {
+                        "assistant_list_previous" => page.saturating_sub(1),
+                        "assistant_list_next" => (page + 1).min(pages.len() - 1),
+                        _ => page,
+                    };
+                    interaction
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(list_page(
+                                &pages,
+                                page,
+                                assistants.len(),
+                            )),
+                        )
+                        .await
+                        .expect("Failed to create interaction response");
+                }
+
+                command
+                    .edit_response(&ctx.http, EditInteractionResponse::new().components(vec![]))
+                    .await
+                    .expect("Failed to edit interaction response");
                 return;
             }
             "tools" => {
                 command.defer(&ctx.http).await.expect("Failed to defer");
-                let tool = options.first().expect("No subcommand").value.clone();
-                let assistant_id = match tool {
-                    CommandDataOptionValue::SubCommand(subcommand) => {
-                        subcommand.first().expect("No assistant id").value.clone()
-                    }
-                    _ => {
-                        let message = CreateInteractionResponse::Message(
-                            CreateInteractionResponseMessage::new().content("Invalid assistant id"),
-                        );
-                        command
-                            .create_response(&ctx.http, message)
-                            .await
-                            .expect("Failed to create interaction response");
+                let name_or_id = subcommand_option(option, "assistant").unwrap_or_default();
+                debug!("Assistant: {}", name_or_id);
+                let assistants = match cache.assistants(openai).await {
+                    Ok(assistants) => assistants,
+                    Err(err) => {
+                        edit_content(ctx, command, format!("Failed to list assistants: {}", err))
+                            .await;
                         return;
                     }
                 };
-                let assistant = match assistant_id {
-                    CommandDataOptionValue::String(assistant_id) => {
-                        debug!("Assistant id: {}", assistant_id);
-                        let assistants = openai.assistants().await;
-                        let assistant = assistants
-                            .iter()
-                            .find(|assistant| assistant.id == assistant_id)
-                            .expect("Invalid assistant id")
-                            .clone();
-                        assistant
-                    }
-                    _ => {
-                        let message = CreateInteractionResponse::Message(
-                            CreateInteractionResponseMessage::new().content("Invalid assistant id"),
-                        );
-                        command
-                            .create_response(&ctx.http, message)
-                            .await
-                            .expect("Failed to create interaction response");
+                let assistant = match find_assistant(&assistants, name_or_id) {
+                    Some(assistant) => assistant.clone(),
+                    None => {
+                        edit_content(ctx, command, format!("Unknown assistant: {}", name_or_id))
+                            .await;
                         return;
                     }
                 };
 
+                let registry = data
+                    .get::<ToolRegistry>()
+                    .expect("Expected ToolRegistry in TypeMap")
+                    .clone();
+
                 let m = command
                     .channel_id
                     .send_message(
@@ -193,8 +422,8 @@ pub async fn run(ctx: &Context, command: &CommandInteraction) {
                             CreateSelectMenu::new(
                                 "tool_select",
                                 CreateSelectMenuKind::String {
-                                    options: available_tools()
-                                        .iter()
+                                    options: registry
+                                        .tools()
                                         .map(|tool| {
                                             CreateSelectMenuOption::new(tool.name(), tool.name())
                                                 .description(tool.description())
@@ -203,7 +432,7 @@ pub async fn run(ctx: &Context, command: &CommandInteraction) {
                                 },
                             )
                             .min_values(1)
-                            .max_values(available_tools().len() as u8),
+                            .max_values(registry.len() as u8),
                         ),
                     )
                     .await
@@ -216,27 +445,23 @@ pub async fn run(ctx: &Context, command: &CommandInteraction) {
                 {
                     Some(interaction) => interaction,
                     None => {
-                        let message = CreateInteractionResponse::Message(
-                            CreateInteractionResponseMessage::new().content("Timed out"),
-                        );
-                        command
-                            .create_response(&ctx.http, message)
-                            .await
-                            .expect("Failed to create interaction response");
+                        edit_content(ctx, command, "Timed out").await;
                         return;
                     }
                 };
+                // Setting the tools can take longer than Discord waits for an answer to the
+                // selection, the outcome goes into the deferred response of the command
+                if let Err(err) = interaction
+                    .create_response(&ctx.http, CreateInteractionResponse::Acknowledge)
+                    .await
+                {
+                    error!("Failed to acknowledge tool selection: {}", err);
+                }
 
                 let tools = match &interaction.data.kind {
                     ComponentInteractionDataKind::StringSelect { values } => values,
                     _ => {
-                        let message = CreateInteractionResponse::Message(
-                            CreateInteractionResponseMessage::new().content("Invalid subcommand"),
-                        );
-                        command
-                            .create_response(&ctx.http, message)
-                            .await
-                            .expect("Failed to create interaction response");
+                        edit_content(ctx, command, "Invalid subcommand").await;
                         return;
                     }
                 };
@@ -248,68 +473,377 @@ pub async fn run(ctx: &Context, command: &CommandInteraction) {
 
                 let tool_definitions = tools
                     .iter()
-                    .map(|tool| {
-                        let available_tools = available_tools();
-                        let tool = available_tools
-                            .iter()
-                            .find(|available_tool| &available_tool.name() == tool)
-                            .expect("Invalid tool");
-                        tool.definition()
-                    })
+                    .filter_map(|tool| registry.get(tool))
+                    .map(|tool| tool.definition())
                     .collect::<Vec<AssistantTools>>();
 
-                openai
+                let content = match openai
                     .set_assistant_tools(&assistant, tool_definitions)
                     .await
-                    .expect("Failed to set assistant tools");
+                {
+                    Ok(_) => {
+                        cache.invalidate().await;
+                        "Successfully set tools".to_string()
+                    }
+                    Err(err) => format!("Failed to set tools: {}", err),
+                };
+                edit_content(ctx, command, content).await;
+            }
+            "edit" => {
+                let name_or_id = subcommand_option(option, "assistant").unwrap_or_default();
+                let assistants = match cache.assistants(openai).await {
+                    Ok(assistants) => assistants,
+                    Err(err) => {
+                        respond(ctx, command, format!("Failed to list assistants: {}", err)).await;
+                        return;
+                    }
+                };
+                let assistant = match find_assistant(&assistants, name_or_id) {
+                    Some(assistant) => assistant,
+                    None => {
+                        respond(ctx, command, format!("Unknown assistant: {}", name_or_id)).await;
+                        return;
+                    }
+                };
+                let instructions = assistant.instructions.clone().unwrap_or_default();
+                if instructions.chars().count() > MAX_INPUT_LENGTH {
+                    respond(
+                        ctx,
+                        command,
+                        "The instructions of this assistant are too long to edit in Discord",
+                    )
+                    .await;
+                    return;
+                }
+
+                let m = command
+                    .quick_modal(
+                        &ctx,
+                        CreateQuickModal::new("Edit assistant")
+                            .field(
+                                CreateInputText::new(InputTextStyle::Short, "Name", "")
+                                    .value(assistant.name.clone().unwrap_or_default()),
+                            )
+                            .field(
+                                CreateInputText::new(InputTextStyle::Short, "Description", "")
+                                    .value(assistant.description.clone().unwrap_or_default())
+                                    .required(false),
+                            )
+                            .field(
+                                CreateInputText::new(InputTextStyle::Short, "Model", "")
+                                    .value(assistant.model.clone()),
+                            )
+                            .field(
+                                CreateInputText::new(InputTextStyle::Paragraph, "Instructions", "")
+                                    .value(instructions)
+                                    .required(false),
+                            )
+                            .timeout(MODAL_TIMEOUT),
+                    )
+                    .await;
+
+                let res = match m {
+                    Ok(Some(res)) => res,
+                    Ok(None) => {
+                        follow_up(ctx, command, "Assistant edit cancelled").await;
+                        return;
+                    }
+                    Err(err) => {
+                        error!("Failed to show the assistant modal: {}", err);
+                        return;
+                    }
+                };
+                let (name, description, model, instructions) = (
+                    &res.inputs[0],
+                    &res.inputs[1],
+                    &res.inputs[2],
+                    &res.inputs[3],
+                );
+
+                let message = match openai
+                    .update_assistant(assistant, name, description, instructions, model)
+                    .await
+                {
+                    Ok(assistant) => {
+                        cache.invalidate().await;
+                        CreateInteractionResponseMessage::new()
+                            .content("Assistant updated")
+                            .embed(assistant_embed(&assistant))
+                    }
+                    Err(err) => CreateInteractionResponseMessage::new()
+                        .content(format!("Failed to update assistant: {}", err)),
+                };
+                if let Err(err) = res
+                    .interaction
+                    .create_response(&ctx.http, CreateInteractionResponse::Message(message))
+                    .await
+                {
+                    error!("Failed to create interaction response: {}", err);
+                }
+                return;
+            }
+            "delete" => {
+                let name_or_id = subcommand_option(option, "assistant").unwrap_or_default();
+                let assistants = match cache.assistants(openai).await {
+                    Ok(assistants) => assistants,
+                    Err(err) => {
+                        respond(ctx, command, format!("Failed to list assistants: {}", err)).await;
+                        return;
+                    }
+                };
+                let assistant = match find_assistant(&assistants, name_or_id) {
+                    Some(assistant) => assistant,
+                    None => {
+                        respond(ctx, command, format!("Unknown assistant: {}", name_or_id)).await;
+                        return;
+                    }
+                };
+                let name = assistant.name.clone().unwrap_or(assistant.id.clone());
+
+                let confirm = CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(format!(
+                            "Delete {} (`{}`)? This can't be undone.",
+                            name, assistant.id
+                        ))
+                        .ephemeral(true)
+                        .components(vec![CreateActionRow::Buttons(vec![
+                            CreateButton::new("assistant_delete_confirm")
+                                .label("Delete")
+                                .style(ButtonStyle::Danger),
+                            CreateButton::new("assistant_delete_cancel")
+                                .label("Cancel")
+                                .style(ButtonStyle::Secondary),
+                        ])]),
+                );
+                command
+                    .create_response(&ctx.http, confirm)
+                    .await
+                    .expect("Failed to create interaction response");
+                let m = command
+                    .get_response(&ctx.http)
+                    .await
+                    .expect("Failed to get interaction response");
+
+                let interaction = match m
+                    .await_component_interaction(&ctx.shard)
+                    .author_id(command.user.id)
+                    .timeout(Duration::from_secs(60))
+                    .await
+                {
+                    Some(interaction) => interaction,
+                    None => {
+                        command
+                            .edit_response(
+                                &ctx.http,
+                                EditInteractionResponse::new()
+                                    .content("Timed out, nothing was deleted")
+                                    .components(vec![]),
+                            )
+                            .await
+                            .expect("Failed to edit interaction response");
+                        return;
+                    }
+                };
 
+                let content = if interaction.data.custom_id == "assistant_delete_confirm" {
+                    match openai.delete_assistant(assistant).await {
+                        Ok(()) => format!("Deleted {}", name),
+                        Err(err) => format!("Failed to delete assistant: {}", err),
+                    }
+                } else {
+                    "Cancelled, nothing was deleted".to_string()
+                };
+                cache.invalidate().await;
                 interaction
                     .create_response(
                         &ctx.http,
-                        CreateInteractionResponse::Message(
+                        CreateInteractionResponse::UpdateMessage(
                             CreateInteractionResponseMessage::new()
-                                .content("Successfully set tools"),
+                                .content(content)
+                                .components(vec![]),
                         ),
                     )
                     .await
                     .expect("Failed to create interaction response");
+                return;
+            }
+            "clone" => {
+                let name_or_id = subcommand_option(option, "assistant").unwrap_or_default();
+                let name = subcommand_option(option, "name").unwrap_or_default();
+                let assistants = match cache.assistants(openai).await {
+                    Ok(assistants) => assistants,
+                    Err(err) => {
+                        respond(ctx, command, format!("Failed to list assistants: {}", err)).await;
+                        return;
+                    }
+                };
+                let assistant = match find_assistant(&assistants, name_or_id) {
+                    Some(assistant) => assistant,
+                    None => {
+                        respond(ctx, command, format!("Unknown assistant: {}", name_or_id)).await;
+                        return;
+                    }
+                };
+
+                command.defer(&ctx.http).await.expect("Failed to defer");
+                let response = match openai.clone_assistant(assistant, name).await {
+                    Ok(clone) => EditInteractionResponse::new()
+                        .content(format!(
+                            "Cloned {} as {} (`{}`)",
+                            name_or_id, name, clone.id
+                        ))
+                        .embed(assistant_embed(&clone)),
+                    Err(err) => EditInteractionResponse::new()
+                        .content(format!("Failed to clone assistant: {}", err)),
+                };
+                cache.invalidate().await;
+                command
+                    .edit_response(&ctx.http, response)
+                    .await
+                    .expect("Failed to edit interaction response");
+                return;
+            }
+            "inspect" => {
+                let name_or_id = subcommand_option(option, "assistant").unwrap_or_default();
+                let assistants = match cache.assistants(openai).await {
+                    Ok(assistants) => assistants,
+                    Err(err) => {
+                        respond(ctx, command, format!("Failed to list assistants: {}", err)).await;
+                        return;
+                    }
+                };
+                let assistant = match find_assistant(&assistants, name_or_id) {
+                    Some(assistant) => assistant,
+                    None => {
+                        respond(ctx, command, format!("Unknown assistant: {}", name_or_id)).await;
+                        return;
+                    }
+                };
+
+                command
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::Message(inspect_message(assistant)),
+                    )
+                    .await
+                    .expect("Failed to create interaction response");
+                return;
+            }
+            "activate" | "deactivate" | "default" => {
+                let name_or_id = subcommand_option(option, "assistant").unwrap_or_default();
+                let assistants = match cache.assistants(openai).await {
+                    Ok(assistants) => assistants,
+                    Err(err) => {
+                        respond(ctx, command, format!("Failed to list assistants: {}", err)).await;
+                        return;
+                    }
+                };
+                let content = match find_assistant(&assistants, name_or_id) {
+                    Some(assistant) => {
+                        let mut config = match channel_configuration(ctx, command.channel_id).await
+                        {
+                            Ok(config) => config,
+                            Err(err) => {
+                                respond(ctx, command, err).await;
+                                return;
+                            }
+                        };
+                        let name = assistant.name.clone().unwrap_or(assistant.id.clone());
+                        let all = assistants
+                            .iter()
+                            .map(|assistant| assistant.id.clone())
+                            .collect::<Vec<String>>();
+                        let content = match option.name.as_str() {
+                            "activate" => {
+                                config.activate(&assistant.id, &all);
+                                format!("{} is now active in this channel", name)
+                            }
+                            "deactivate" => {
+                                config.deactivate(&assistant.id, &all);
+                                format!("{} no longer responds in this channel", name)
+                            }
+                            _ => {
+                                config.default_assistant = Some(assistant.id.clone());
+                                format!("{} is now the default assistant of this channel", name)
+                            }
+                        };
+                        match save_channel_configuration(ctx, command.channel_id, &config).await {
+                            Ok(()) => content,
+                            Err(err) => err,
+                        }
+                    }
+                    None => format!("Unknown assistant: {}", name_or_id),
+                };
 
+                let message = CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new().content(content),
+                );
+                command
+                    .create_response(&ctx.http, message)
+                    .await
+                    .expect("Failed to create interaction response");
+                return;
             }
             "create" => {
+                let model = subcommand_option(option, "model").unwrap_or(DEFAULT_MODEL);
+                let avatar = subcommand_option(option, "avatar");
+
                 let m = command
                     .quick_modal(
                         &ctx,
                         CreateQuickModal::new("Assistant details")
                             .short_field("Name")
                             .short_field("Description")
-                            .paragraph_field("Instructions"),
+                            .paragraph_field("Instructions")
+                            .timeout(MODAL_TIMEOUT),
                     )
-                    .await
-                    .expect("Failed to send message");
+                    .await;
+                let m = match m {
+                    Ok(m) => m,
+                    Err(err) => {
+                        error!("Failed to show the assistant modal: {}", err);
+                        return;
+                    }
+                };
 
                 match m {
                     Some(res) => {
                         let (name, description, instructions) =
                             (&res.inputs[0], &res.inputs[1], &res.inputs[2]);
 
-                        let message = CreateInteractionResponse::Message(
-                            CreateInteractionResponseMessage::new()
-                                .content(format!("Assistant created! Say hi to {}", name)),
-                        );
-                        res.interaction
-                            .create_response(&ctx.http, message)
+                        let tools = data
+                            .get::<ToolRegistry>()
+                            .expect("Expected ToolRegistry in TypeMap")
+                            .tools()
+                            .map(|tool| tool.definition())
+                            .collect::<Vec<AssistantTools>>();
+
+                        let message = match openai
+                            .create_assistant(name, description, instructions, model, tools, avatar)
+                            .await
+                        {
+                            Ok(assistant) => CreateInteractionResponseMessage::new()
+                                .content(format!(
+                                    "Assistant created! Say hi to {} (`{}`)",
+                                    name, assistant.id
+                                ))
+                                .embed(assistant_embed(&assistant)),
+                            Err(err) => CreateInteractionResponseMessage::new()
+                                .content(format!("Failed to create assistant: {}", err)),
+                        };
+                        cache.invalidate().await;
+                        if let Err(err) = res
+                            .interaction
+                            .create_response(&ctx.http, CreateInteractionResponse::Message(message))
                             .await
-                            .expect("Failed to create interaction response");
+                        {
+                            error!("Failed to create interaction response: {}", err);
+                        }
                     }
                     None => {
-                        let message = CreateInteractionResponse::Message(
-                            CreateInteractionResponseMessage::new()
-                                .content("Assistant creation cancelled"),
-                        );
-                        command
-                            .create_response(&ctx.http, message)
-                            .await
-                            .expect("Failed to create interaction response");
+                        // The modal answered the command, the cancellation is a followup
+                        follow_up(ctx, command, "Assistant creation cancelled").await;
                         return;
                     }
                 }
@@ -328,6 +862,52 @@ pub async fn run(ctx: &Context, command: &CommandInteraction) {
     }
 }
 
+/// Suggests assistants whose name or id contains what the user typed so far.
+pub async fn autocomplete(ctx: &Context, interaction: &CommandInteraction) {
+    let typed = match interaction.data.autocomplete() {
+        Some(option) if option.name == "assistant" => option.value.to_lowercase(),
+        _ => return,
+    };
+
+    let assistants = {
+        let data = ctx.data.read().await;
+        let openai = data.get::<OpenAI>().expect("Expected OpenAI in TypeMap");
+        data.get::<AssistantCache>()
+            .expect("Expected AssistantCache in TypeMap")
+            .assistants(openai)
+            .await
+    };
+    let assistants = match assistants {
+        Ok(assistants) => assistants,
+        Err(err) => {
+            error!("Failed to list assistants: {}", err);
+            return;
+        }
+    };
+
+    let response = assistants
+        .iter()
+        .filter(|assistant| {
+            assistant.id.to_lowercase().contains(&typed)
+                || assistant
+                    .name
+                    .as_ref()
+                    .is_some_and(|name| name.to_lowercase().contains(&typed))
+        })
+        .take(MAX_AUTOCOMPLETE_CHOICES)
+        .fold(CreateAutocompleteResponse::new(), |response, assistant| {
+            let name = assistant.name.clone().unwrap_or(assistant.id.clone());
+            response.add_string_choice(name.chars().take(100).collect::<String>(), &assistant.id)
+        });
+
+    if let Err(err) = interaction
+        .create_response(&ctx.http, CreateInteractionResponse::Autocomplete(response))
+        .await
+    {
+        error!("Failed to send autocomplete response: {}", err);
+    }
+}
+
 pub fn register() -> CreateCommand {
     CreateCommand::new("assistant")
         .description("Manage your assistants")
@@ -335,7 +915,7 @@ pub fn register() -> CreateCommand {
             CreateCommandOption::new(
                 CommandOptionType::SubCommand,
                 "default",
-                "Set your default assistant",
+                "Set the default assistant of this channel",
             )
             .add_sub_option(
                 CreateCommandOption::new(
@@ -343,7 +923,40 @@ pub fn register() -> CreateCommand {
                     "assistant",
                     "The assistant to set as default",
                 )
-                .required(true),
+                .required(true)
+                .set_autocomplete(true),
+            ),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "activate",
+                "Let an assistant respond in this channel, new assistants are inactive from then on",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "assistant",
+                    "The assistant to activate",
+                )
+                .required(true)
+                .set_autocomplete(true),
+            ),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "deactivate",
+                "Stop an assistant from responding here, new assistants are inactive from then on",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "assistant",
+                    "The assistant to deactivate",
+                )
+                .required(true)
+                .set_autocomplete(true),
             ),
         )
         .add_option(
@@ -358,22 +971,139 @@ pub fn register() -> CreateCommand {
                     "assistant",
                     "The assistant to set the image for",
                 )
+                .required(true)
+                .set_autocomplete(true),
+            ),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "list",
+                "List available assistants",
+            )
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "model",
+                "Only list assistants running on this model",
+            ))
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "tool",
+                "Only list assistants with this tool enabled",
+            )),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "create",
+                "Create a new assistant",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "model",
+                    "The model the assistant runs on",
+                )
+                .add_string_choice("GPT-4 Turbo", "gpt-4-1106-preview")
+                .add_string_choice("GPT-4", "gpt-4")
+                .add_string_choice("GPT-3.5 Turbo", "gpt-3.5-turbo-1106"),
+            )
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "avatar",
+                "Url of the assistant profile image",
+            )),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "image",
+                "Set assistant profile image",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "assistant",
+                    "The name or id of the assistant",
+                )
+                .required(true)
+                .set_autocomplete(true),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "url",
+                    "Url of the new profile image",
+                )
                 .required(true),
             ),
         )
-        .add_option(CreateCommandOption::new(
-            CommandOptionType::SubCommand,
-            "list",
-            "List available assistants",
-        ))
-        .add_option(CreateCommandOption::new(
-            CommandOptionType::SubCommand,
-            "create",
-            "Create a new assistant",
-        ))
-        .add_option(CreateCommandOption::new(
-            CommandOptionType::SubCommand,
-            "image",
-            "Set assistant profile image",
-        ))
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "edit",
+                "Edit the name, description, model and instructions of an assistant",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "assistant",
+                    "The name or id of the assistant",
+                )
+                .required(true)
+                .set_autocomplete(true),
+            ),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "delete",
+                "Delete an assistant",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "assistant",
+                    "The name or id of the assistant",
+                )
+                .required(true)
+                .set_autocomplete(true),
+            ),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "clone",
+                "Copy an assistant under a new name",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "assistant",
+                    "The name or id of the assistant",
+                )
+                .required(true)
+                .set_autocomplete(true),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(CommandOptionType::String, "name", "The name of the copy")
+                    .required(true),
+            ),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "inspect",
+                "Show the instructions, tools and metadata of an assistant",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "assistant",
+                    "The name or id of the assistant",
+                )
+                .required(true)
+                .set_autocomplete(true),
+            ),
+        )
 }
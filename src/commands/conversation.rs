@@ -0,0 +1,156 @@
+use serenity::{
+    all::{CommandDataOption, CommandDataOptionValue, CommandInteraction, CommandOptionType},
+    builder::{CreateCommand, CreateCommandOption},
+    client::Context,
+};
+
+use crate::{
+    commands::assistant::{
+        channel_configuration, find_assistant, respond, save_channel_configuration,
+        subcommand_option,
+    },
+    conversation::{Conversations, MAX_ROUNDS},
+    openai::{AssistantCache, OpenAI},
+};
+
+fn rounds_option(option: &CommandDataOption) -> Option<i64> {
+    match &option.value {
+        CommandDataOptionValue::SubCommand(options) => options
+            .iter()
+            .find(|option| option.name == "rounds")
+            .and_then(|option| option.value.as_i64()),
+        _ => None,
+    }
+}
+
+async fn set_moderator(ctx: &Context, name_or_id: Option<&str>) -> Result<Option<String>, String> {
+    let name_or_id = match name_or_id {
+        Some(name_or_id) => name_or_id,
+        None => return Ok(None),
+    };
+
+    let data = ctx.data.read().await;
+    let openai = data.get::<OpenAI>().expect("Expected OpenAI in TypeMap");
+    let assistants = data
+        .get::<AssistantCache>()
+        .expect("Expected AssistantCache in TypeMap")
+        .assistants(openai)
+        .await
+        .map_err(|err| format!("Failed to list assistants: {}", err))?;
+    find_assistant(&assistants, name_or_id)
+        .map(|assistant| Some(assistant.id.clone()))
+        .ok_or_else(|| format!("Unknown assistant: {}", name_or_id))
+}
+
+pub async fn run(ctx: &Context, command: &CommandInteraction) {
+    let option = match command.data.options.first() {
+        Some(option) => option,
+        None => {
+            respond(ctx, command, "Invalid subcommand").await;
+            return;
+        }
+    };
+    let mut config = match channel_configuration(ctx, command.channel_id).await {
+        Ok(config) => config,
+        Err(err) => {
+            respond(ctx, command, err).await;
+            return;
+        }
+    };
+
+    let content = match option.name.as_str() {
+        "rounds" => {
+            let rounds = rounds_option(option).unwrap_or_default();
+            config.max_rounds = rounds.clamp(0, MAX_ROUNDS as i64) as u32;
+            match config.max_rounds {
+                0 => "Assistants no longer answer each other".to_string(),
+                rounds => format!("Assistants answer each other for up to {} turns", rounds),
+            }
+        }
+        "moderator" => match set_moderator(ctx, subcommand_option(option, "assistant")).await {
+            Ok(Some(moderator)) => {
+                config.moderator = Some(moderator);
+                let mut content = "The moderator now picks who speaks next".to_string();
+                if config.max_rounds == 0 {
+                    content.push_str(
+                        "\nAssistants don't answer each other yet, allow it with /conversation rounds",
+                    );
+                }
+                content
+            }
+            Ok(None) => {
+                config.moderator = None;
+                "Assistants now answer whoever addresses them by name".to_string()
+            }
+            Err(err) => {
+                respond(ctx, command, err).await;
+                return;
+            }
+        },
+        "stop" => {
+            let data = ctx.data.read().await;
+            data.get::<Conversations>()
+                .expect("Expected Conversations in TypeMap")
+                .interrupt(command.channel_id.get());
+            respond(
+                ctx,
+                command,
+                "Stopping the conversation after the current turn",
+            )
+            .await;
+            return;
+        }
+        _ => {
+            respond(ctx, command, "Invalid subcommand").await;
+            return;
+        }
+    };
+
+    let content = match save_channel_configuration(ctx, command.channel_id, &config).await {
+        Ok(()) => content,
+        Err(err) => err,
+    };
+    respond(ctx, command, content).await;
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("conversation")
+        .description("Configure conversations between assistants in this channel")
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "rounds",
+                "How many turns assistants may take answering each other",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "rounds",
+                    "Number of turns, 0 turns conversations between assistants off",
+                )
+                .required(true)
+                .min_int_value(0)
+                .max_int_value(MAX_ROUNDS as u64),
+            ),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "moderator",
+                "Let an assistant pick who speaks next, or go back to addressing by name",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "assistant",
+                    "The moderator, leave empty to remove it",
+                )
+                .set_autocomplete(true),
+            ),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::SubCommand,
+            "stop",
+            "Interrupt the conversation between assistants",
+        ))
+}
@@ -1,8 +1,10 @@
 pub mod assistant;
+pub mod conversation;
 pub mod image;
 pub mod join_voice;
 pub mod register;
 pub mod reset;
 pub mod thread;
+pub mod triggers;
 pub mod tts;
 
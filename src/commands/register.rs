@@ -1,27 +1,72 @@
-
-use serenity::{all::CommandInteraction, client::Context, builder::{CreateInteractionResponse, CreateInteractionResponseMessage, CreateCommand}};
+use chrono_tz::Tz;
+use serenity::{
+    all::{CommandDataOptionValue, CommandInteraction, CommandOptionType},
+    builder::{
+        CreateCommand, CreateCommandOption, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    client::Context,
+};
 
 use crate::database::users::{User, UserStore};
 
 pub async fn run(ctx: &Context, command: &CommandInteraction) {
+    let timezone = command
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == "timezone")
+        .and_then(|option| match &option.value {
+            CommandDataOptionValue::String(timezone) => Some(timezone.trim().to_string()),
+            _ => None,
+        });
+
+    if let Some(timezone) = &timezone {
+        if timezone.parse::<Tz>().is_err() {
+            let message = CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(format!(
+                        "Unknown timezone {}, use a name like Europe/Brussels",
+                        timezone
+                    ))
+                    .ephemeral(true),
+            );
+            command
+                .create_response(&ctx.http, message)
+                .await
+                .expect("Failed to create interaction response");
+            return;
+        }
+    }
+
     let data_read = ctx.data.read().await;
     let user_store = data_read
         .get::<UserStore>()
         .expect("Expected UserStore in TypeMap");
     let user_store = user_store.read().await;
+
+    let nickname = match command.guild_id {
+        Some(guild_id) => command.user.nick_in(&ctx.http, guild_id).await,
+        None => None,
+    };
+    let mut user = User::new(
+        command.user.id.get().to_string(),
+        command.user.name.clone(),
+        nickname,
+        None,
+    );
+    user.timezone = timezone.clone();
     user_store
-        .register_user(&User::new(
-            command.user.id.get().to_string(),
-            command.user.name.clone(),
-            command
-                .user
-                .nick_in(&ctx.http, command.guild_id.expect("No guild"))
-                .await,
-            None,
-        ))
+        .register_user(&user)
         .expect("Failed to register user");
 
-    let message = CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content("Registered!"));
+    let content = match timezone {
+        Some(timezone) => format!("Registered with timezone {}!", timezone),
+        None => "Registered!".to_string(),
+    };
+    let message = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new().content(content),
+    );
 
     command
         .create_response(&ctx.http, message)
@@ -30,5 +75,11 @@ pub async fn run(ctx: &Context, command: &CommandInteraction) {
 }
 
 pub fn register() -> CreateCommand {
-    CreateCommand::new("register").description("Register yourself")
+    CreateCommand::new("register")
+        .description("Register yourself")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "timezone",
+            "Your IANA timezone, e.g. Europe/Brussels",
+        ))
 }
@@ -4,10 +4,16 @@ use serenity::{
     client::Context,
 };
 
-use crate::database::channels::reset_channel_thread;
+use crate::database::channels::ChannelStore;
 
 pub async fn run(ctx: &Context, command: &CommandInteraction) {
-    let message = match reset_channel_thread(command.channel_id.get()).await {
+    let channels = {
+        let data = ctx.data.read().await;
+        data.get::<ChannelStore>()
+            .expect("Expected ChannelStore in TypeMap")
+            .clone()
+    };
+    let message = match channels.reset_thread(command.channel_id.get()).await {
         Ok(()) => CreateInteractionResponse::Message(
             CreateInteractionResponseMessage::new().content("Thread has been reset!"),
         ),
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use serenity::{
+    all::{CommandInteraction, CommandOptionType},
+    builder::{CreateCommand, CreateCommandOption, EditInteractionResponse},
+    client::Context,
+};
+
+use crate::{
+    bot::SplitToVector,
+    commands::assistant::{channel_configuration, respond},
+    database::channels::ChannelStore,
+    openai::{OpenAI, ThreadStore},
+    thread::OpenAIThread,
+};
+
+/// How many messages of the parent conversation go into the summary.
+const SEED_MESSAGES: u32 = 50;
+
+async fn summarize_parent(ctx: &Context, parent_thread: &str) -> Result<String, String> {
+    let messages = OpenAIThread::from_existing(parent_thread)
+        .messages(SEED_MESSAGES)
+        .await
+        .map_err(|err| format!("Failed to get the parent conversation: {}", err))?;
+    if messages.is_empty() {
+        return Err("The parent conversation is empty, nothing to summarize".to_string());
+    }
+
+    let data = ctx.data.read().await;
+    let openai = data.get::<OpenAI>().expect("Expected OpenAI in TypeMap");
+    openai
+        .summarize(&messages.join("\n"))
+        .await
+        .map_err(|err| format!("Failed to summarize the parent conversation: {}", err))
+}
+
+/// The thread from the store, so the summary queues up behind a running assistant.
+async fn thread(ctx: &Context, thread_id: &str) -> Arc<OpenAIThread> {
+    let data = ctx.data.read().await;
+    let store = data
+        .get::<ThreadStore>()
+        .expect("Expected ThreadStore in TypeMap")
+        .lock()
+        .await;
+    store.get(thread_id).expect("Failed to get thread")
+}
+
+async fn seed(ctx: &Context, command: &CommandInteraction) {
+    let config = match channel_configuration(ctx, command.channel_id).await {
+        Ok(config) => config,
+        Err(err) => {
+            respond(ctx, command, err).await;
+            return;
+        }
+    };
+    let parent = match config.parent_channel {
+        Some(parent_id) => {
+            let data = ctx.data.read().await;
+            data.get::<ChannelStore>()
+                .expect("Expected ChannelStore in TypeMap")
+                .get(parent_id)
+                .map(|parent| parent.map(|parent| (parent_id, parent)))
+        }
+        None => Ok(None),
+    };
+    let (parent_id, parent) = match parent {
+        Ok(Some(parent)) => parent,
+        Ok(None) => {
+            respond(ctx, command, "Seeding only works in a thread or forum post").await;
+            return;
+        }
+        Err(err) => {
+            respond(ctx, command, err).await;
+            return;
+        }
+    };
+
+    command.defer(&ctx.http).await.expect("Failed to defer");
+    let content = match summarize_parent(ctx, &parent.thread).await {
+        Ok(summary) => {
+            let added = thread(ctx, &config.thread)
+                .await
+                .add_message(format!(
+                    "Summary of the conversation in the parent channel:\n{}",
+                    summary
+                ))
+                .await;
+            match added {
+                Ok(()) => format!(
+                    "Seeded this thread with a summary of <#{}>:\n{}",
+                    parent_id, summary
+                ),
+                Err(err) => err,
+            }
+        }
+        Err(err) => err,
+    };
+
+    let content = content
+        .split_to_vector(2000)
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    command
+        .edit_response(&ctx.http, EditInteractionResponse::new().content(content))
+        .await
+        .expect("Failed to edit interaction response");
+}
+
+pub async fn run(ctx: &Context, command: &CommandInteraction) {
+    match command
+        .data
+        .options
+        .first()
+        .map(|option| option.name.as_str())
+    {
+        Some("seed") => seed(ctx, command).await,
+        _ => respond(ctx, command, "Invalid subcommand").await,
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("thread")
+        .description("Manage the conversation of a Discord thread")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::SubCommand,
+            "seed",
+            "Start this thread with a summary of the parent channel's conversation",
+        ))
+}
@@ -0,0 +1,205 @@
+use serenity::{
+    all::{CommandInteraction, CommandOptionType},
+    builder::{CreateCommand, CreateCommandOption},
+    client::Context,
+};
+
+use crate::{
+    commands::assistant::{
+        channel_configuration, find_assistant, respond, save_channel_configuration,
+        subcommand_option,
+    },
+    openai::{AssistantCache, OpenAI},
+    triggers::{compile_pattern, default_triggers, Trigger},
+};
+
+fn describe(trigger: &Trigger) -> String {
+    match trigger {
+        Trigger::Name => "name: an assistant is addressed by its name".to_string(),
+        Trigger::Mention => "mention: the default assistant answers @mentions".to_string(),
+        Trigger::Reply => "reply: an assistant answers replies to its messages".to_string(),
+        Trigger::Regex {
+            pattern,
+            assistant_id,
+        } => format!("regex: `{}` wakes {}", pattern, assistant_id),
+        Trigger::Always => "always: the default assistant answers every message".to_string(),
+    }
+}
+
+fn trigger_list(triggers: &[Trigger]) -> String {
+    if triggers.is_empty() {
+        return "No triggers, assistants won't respond in this channel".to_string();
+    }
+    triggers
+        .iter()
+        .enumerate()
+        .map(|(index, trigger)| format!("{}. {}", index + 1, describe(trigger)))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+async fn regex_trigger(
+    ctx: &Context,
+    pattern: Option<&str>,
+    assistant: Option<&str>,
+) -> Result<Trigger, String> {
+    let (pattern, assistant) = match (pattern, assistant) {
+        (Some(pattern), Some(assistant)) => (pattern, assistant),
+        _ => return Err("A regex trigger needs a pattern and an assistant".to_string()),
+    };
+    compile_pattern(pattern).map_err(|err| format!("Invalid pattern: {}", err))?;
+
+    let data = ctx.data.read().await;
+    let openai = data.get::<OpenAI>().expect("Expected OpenAI in TypeMap");
+    let assistants = data
+        .get::<AssistantCache>()
+        .expect("Expected AssistantCache in TypeMap")
+        .assistants(openai)
+        .await
+        .map_err(|err| format!("Failed to list assistants: {}", err))?;
+    let assistant = find_assistant(&assistants, assistant)
+        .ok_or_else(|| format!("Unknown assistant: {}", assistant))?;
+
+    Ok(Trigger::Regex {
+        pattern: pattern.to_string(),
+        assistant_id: assistant.id.clone(),
+    })
+}
+
+pub async fn run(ctx: &Context, command: &CommandInteraction) {
+    let option = match command.data.options.first() {
+        Some(option) => option,
+        None => {
+            respond(ctx, command, "Invalid subcommand").await;
+            return;
+        }
+    };
+    let mut config = match channel_configuration(ctx, command.channel_id).await {
+        Ok(config) => config,
+        Err(err) => {
+            respond(ctx, command, err).await;
+            return;
+        }
+    };
+    let kind = subcommand_option(option, "kind").unwrap_or_default();
+    let pattern = subcommand_option(option, "pattern");
+
+    let content = match option.name.as_str() {
+        "list" => {
+            respond(ctx, command, trigger_list(&config.triggers)).await;
+            return;
+        }
+        "add" => {
+            let trigger = match kind {
+                "name" => Ok(Trigger::Name),
+                "mention" => Ok(Trigger::Mention),
+                "reply" => Ok(Trigger::Reply),
+                "always" => Ok(Trigger::Always),
+                "regex" => {
+                    regex_trigger(ctx, pattern, subcommand_option(option, "assistant")).await
+                }
+                _ => Err(format!("Unknown trigger: {}", kind)),
+            };
+            match trigger {
+                Ok(trigger) if config.triggers.contains(&trigger) => {
+                    format!("Trigger {} is already enabled", trigger.kind())
+                }
+                Ok(trigger) => {
+                    let mut content = format!("Added trigger {}", describe(&trigger));
+                    if matches!(trigger, Trigger::Mention | Trigger::Always)
+                        && config.default_assistant.is_none()
+                    {
+                        content.push_str(
+                            "\nThis trigger needs a default assistant, set one with /assistant default",
+                        );
+                    }
+                    config.triggers.push(trigger);
+                    content
+                }
+                Err(err) => {
+                    respond(ctx, command, err).await;
+                    return;
+                }
+            }
+        }
+        "remove" => {
+            let before = config.triggers.len();
+            config.triggers.retain(|trigger| match (trigger, pattern) {
+                (Trigger::Regex { pattern, .. }, Some(removed)) if kind == "regex" => {
+                    pattern != removed
+                }
+                (trigger, _) => trigger.kind() != kind,
+            });
+            if config.triggers.len() == before {
+                respond(ctx, command, format!("No {} trigger to remove", kind)).await;
+                return;
+            }
+            format!("Removed {} trigger", kind)
+        }
+        "reset" => {
+            config.triggers = default_triggers();
+            "Triggers reset to the defaults".to_string()
+        }
+        _ => {
+            respond(ctx, command, "Invalid subcommand").await;
+            return;
+        }
+    };
+
+    let content = match save_channel_configuration(ctx, command.channel_id, &config).await {
+        Ok(()) => format!("{}\n\n{}", content, trigger_list(&config.triggers)),
+        Err(err) => err,
+    };
+    respond(ctx, command, content).await;
+}
+
+fn kind_option(description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::String, "kind", description)
+        .required(true)
+        .add_string_choice("Name", "name")
+        .add_string_choice("Mention", "mention")
+        .add_string_choice("Reply", "reply")
+        .add_string_choice("Regex", "regex")
+        .add_string_choice("Always", "always")
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("triggers")
+        .description("Configure when assistants respond in this channel")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::SubCommand,
+            "list",
+            "List the triggers of this channel",
+        ))
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "add", "Add a trigger")
+                .add_sub_option(kind_option("The kind of trigger to add"))
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "pattern",
+                    "Regex that wakes the assistant, for regex triggers",
+                ))
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "assistant",
+                        "The assistant woken by the regex",
+                    )
+                    .set_autocomplete(true),
+                ),
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "remove", "Remove a trigger")
+                .add_sub_option(kind_option("The kind of trigger to remove"))
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "pattern",
+                    "Only remove the regex trigger with this pattern",
+                )),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::SubCommand,
+            "reset",
+            "Go back to the default triggers",
+        ))
+}
@@ -0,0 +1,37 @@
+use std::{env, fmt::Display, str::FromStr};
+
+use log::error;
+
+/// Reads the environment variable `name`, falling back to `default` when it isn't set or
+/// can't be parsed.
+pub fn env_or<T>(name: &str, default: T) -> T
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|err| {
+            error!("Invalid {} '{}', using the default: {}", name, value, err);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_or() {
+        env::set_var("CONFIG_TEST_NUMBER", " 42 ");
+        env::set_var("CONFIG_TEST_INVALID", "many");
+        assert_eq!(env_or("CONFIG_TEST_NUMBER", 7u64), 42);
+        assert_eq!(env_or("CONFIG_TEST_INVALID", 7u64), 7);
+        assert_eq!(env_or("CONFIG_TEST_UNSET", 7u64), 7);
+        assert_eq!(
+            env_or("CONFIG_TEST_UNSET", "default".to_string()),
+            "default"
+        );
+    }
+}
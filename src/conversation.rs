@@ -0,0 +1,266 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use async_openai::types::AssistantObject;
+use log::{debug, error};
+use serenity::{
+    client::Context,
+    model::{channel::Message, id::UserId},
+};
+
+use crate::{
+    bot::assistant_response,
+    database::channels::ChannelConfiguration,
+    openai::OpenAI,
+    thread::OpenAIThread,
+    triggers::{triggered, webhook_id, IncomingMessage, Trigger},
+};
+
+/// Upper bound for the number of rounds a channel can configure.
+pub const MAX_ROUNDS: u32 = 20;
+
+/// How many messages the moderator reads before picking the next speaker.
+const MODERATOR_CONTEXT: u32 = 20;
+
+/// Keeps track of the conversation between assistants running in each channel. Every human
+/// message begins a new conversation, which interrupts the one that was running.
+#[derive(Default)]
+pub struct Conversations {
+    generations: Mutex<HashMap<u64, u64>>,
+}
+
+impl Conversations {
+    /// Begins a new conversation in the channel and returns its generation.
+    pub fn begin(&self, channel_id: u64) -> u64 {
+        let mut generations = self
+            .generations
+            .lock()
+            .expect("Failed to lock conversations");
+        let generation = generations.entry(channel_id).or_default();
+        *generation += 1;
+        *generation
+    }
+
+    /// Stops the conversation running in the channel after the current turn.
+    pub fn interrupt(&self, channel_id: u64) {
+        self.begin(channel_id);
+    }
+
+    /// Whether `generation` is still the conversation running in the channel.
+    pub fn is_current(&self, channel_id: u64, generation: u64) -> bool {
+        self.generations
+            .lock()
+            .expect("Failed to lock conversations")
+            .get(&channel_id)
+            == Some(&generation)
+    }
+}
+
+/// Whether the assistants posted `msg` themselves, through the channel webhook or as the
+/// bot in DMs. Their replies are in the OpenAI thread already.
+pub fn is_echo(msg: &Message, channel_config: &ChannelConfiguration, bot_id: UserId) -> bool {
+    let own_webhook = channel_config.webhook.as_deref().and_then(webhook_id);
+    msg.author.id == bot_id
+        || (own_webhook.is_some() && msg.webhook_id.map(|id| id.get()) == own_webhook)
+}
+
+/// The other assistants `speaker` addresses by name in `reply`.
+pub fn addressed<'a>(
+    reply: &str,
+    speaker: &AssistantObject,
+    assistants: &[&'a AssistantObject],
+) -> Vec<&'a AssistantObject> {
+    let message = IncomingMessage {
+        content: reply,
+        ..Default::default()
+    };
+    triggered(&[Trigger::Name], &message, assistants, None)
+        .into_iter()
+        .filter(|assistant| assistant.id != speaker.id)
+        .collect()
+}
+
+fn moderator_prompt(moderator: &AssistantObject, candidates: &[&AssistantObject]) -> String {
+    let names = candidates
+        .iter()
+        .filter_map(|assistant| assistant.name.as_deref())
+        .collect::<Vec<&str>>()
+        .join(", ");
+    format!(
+        "{}\n\nYou moderate the following conversation between {}. Decide who speaks next. \
+        Answer with only their name, or with nobody when the conversation is finished or \
+        waits for a human.",
+        moderator.instructions.as_deref().unwrap_or_default(),
+        names
+    )
+    .trim_start()
+    .to_string()
+}
+
+/// Finds the assistant the moderator picked, `None` when it picked nobody.
+fn chosen<'a>(answer: &str, candidates: &[&'a AssistantObject]) -> Option<&'a AssistantObject> {
+    let answer = answer.trim_matches(|c: char| !c.is_alphanumeric());
+    candidates.iter().copied().find(|assistant| {
+        assistant
+            .name
+            .as_ref()
+            .is_some_and(|name| name.eq_ignore_ascii_case(answer))
+    })
+}
+
+async fn next_speaker<'a>(
+    ctx: &Context,
+    thread: &OpenAIThread,
+    moderator: &AssistantObject,
+    candidates: &[&'a AssistantObject],
+) -> Option<&'a AssistantObject> {
+    let transcript = match thread.messages(MODERATOR_CONTEXT).await {
+        Ok(messages) => messages.join("\n"),
+        Err(err) => {
+            error!("Failed to get messages for the moderator: {}", err);
+            return None;
+        }
+    };
+
+    let data = ctx.data.read().await;
+    let openai = data.get::<OpenAI>().expect("Expected OpenAI in TypeMap");
+    let prompt = moderator_prompt(moderator, candidates);
+    match openai
+        .complete(&moderator.model, &prompt, &transcript)
+        .await
+    {
+        Ok(answer) => {
+            debug!("Moderator picked: {}", answer);
+            chosen(&answer, candidates)
+        }
+        Err(err) => {
+            error!("Moderator failed to pick a speaker: {}", err);
+            None
+        }
+    }
+}
+
+/// Lets `first` answer the human message, then keeps the conversation going for at most
+/// `max_rounds` turns. Assistants answer the ones that address them by name, or whoever
+/// the moderator picks. A new human message in the channel interrupts the conversation.
+pub async fn converse(
+    msg: &Message,
+    ctx: &Context,
+    thread: &OpenAIThread,
+    channel_config: &ChannelConfiguration,
+    assistants: &[AssistantObject],
+    first: Vec<&AssistantObject>,
+    generation: u64,
+) {
+    let conversations = {
+        let data = ctx.data.read().await;
+        data.get::<Conversations>()
+            .expect("Expected Conversations in TypeMap")
+            .clone()
+    };
+    let channel_id = msg.channel_id.get();
+    let active = assistants
+        .iter()
+        .filter(|assistant| channel_config.is_active(&assistant.id))
+        .collect::<Vec<&AssistantObject>>();
+    let moderator = channel_config
+        .moderator
+        .as_deref()
+        .and_then(|id| assistants.iter().find(|assistant| assistant.id == id));
+
+    let mut queue: VecDeque<&AssistantObject> = first.into();
+    let mut rounds = 0;
+    while let Some(speaker) = queue.pop_front() {
+        if !conversations.is_current(channel_id, generation) {
+            debug!("Conversation in channel {} was interrupted", channel_id);
+            return;
+        }
+
+        let reply = assistant_response(msg, ctx, thread, channel_config, speaker).await;
+        if rounds >= channel_config.max_rounds {
+            continue;
+        }
+
+        let next = match moderator {
+            Some(moderator) if queue.is_empty() => {
+                let candidates = active
+                    .iter()
+                    .copied()
+                    .filter(|assistant| assistant.id != speaker.id)
+                    .collect::<Vec<&AssistantObject>>();
+                next_speaker(ctx, thread, moderator, &candidates)
+                    .await
+                    .into_iter()
+                    .collect()
+            }
+            Some(_) => vec![],
+            None => addressed(&reply, speaker, &active),
+        };
+        for assistant in next {
+            if rounds < channel_config.max_rounds
+                && !queue.iter().any(|queued| queued.id == assistant.id)
+            {
+                queue.push_back(assistant);
+                rounds += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assistant;
+
+    #[test]
+    fn test_conversation_generations() {
+        let conversations = Conversations::default();
+        let first = conversations.begin(1);
+        assert!(conversations.is_current(1, first));
+        assert!(!conversations.is_current(2, first));
+
+        let second = conversations.begin(2);
+        assert!(conversations.is_current(1, first));
+        assert!(conversations.is_current(2, second));
+
+        conversations.interrupt(1);
+        assert!(!conversations.is_current(1, first));
+    }
+
+    #[test]
+    fn test_addressed_skips_speaker() {
+        let ada = assistant("asst_ada", "Ada");
+        let bob = assistant("asst_bob", "Bob");
+        let assistants = vec![&ada, &bob];
+
+        let ids = |reply| {
+            addressed(reply, &ada, &assistants)
+                .into_iter()
+                .map(|assistant| assistant.id.as_str())
+                .collect::<Vec<&str>>()
+        };
+        assert_eq!(ids("What do you think, Bob?"), vec!["asst_bob"]);
+        assert!(ids("I, Ada, think so").is_empty());
+        assert!(ids("No names here").is_empty());
+    }
+
+    #[test]
+    fn test_chosen() {
+        let ada = assistant("asst_ada", "Ada");
+        let bob = assistant("asst_bob", "Bob");
+        let candidates = vec![&ada, &bob];
+
+        assert_eq!(
+            chosen("Bob", &candidates).map(|a| a.id.as_str()),
+            Some("asst_bob")
+        );
+        assert_eq!(
+            chosen(" ada.\n", &candidates).map(|a| a.id.as_str()),
+            Some("asst_ada")
+        );
+        assert!(chosen("nobody", &candidates).is_none());
+        assert!(chosen("Bob and Ada", &candidates).is_none());
+    }
+}
@@ -59,26 +59,26 @@ impl Minio {
         }
     }
 
-    pub async fn upload_image(&self, image_path: &str) -> Result<String, reqwest::Error> {
+    pub async fn upload_image(&self, image_path: &str) -> Result<String, String> {
         let bucket = "images";
-        self.ensure_bucket_exists(bucket).await;
+        self.ensure_bucket_exists(bucket).await?;
         let filename = rand::random::<u64>().to_string();
         let filename = format!("{}.png", filename);
         let res = self
             .minio_client
             .upload_object(
                 &mut UploadObjectArgs::<SseCustomerKey>::new(bucket, &filename, image_path)
-                    .unwrap(),
+                    .map_err(|err| format!("Invalid upload arguments: {:?}", err))?,
             )
             .await
-            .unwrap();
+            .map_err(|err| format!("Failed to upload image: {:?}", err))?;
         debug!("uploaded image: {:?}", res.location);
         Ok(format!("{}/{}/{}", self.base_url, bucket, filename))
     }
 
-    pub async fn upload_mp3(&self, mp3_path: &str) -> Result<String, reqwest::Error> {
+    pub async fn upload_mp3(&self, mp3_path: &str) -> Result<String, String> {
         let bucket = "audio";
-        self.ensure_bucket_exists(bucket).await;
+        self.ensure_bucket_exists(bucket).await?;
 
         let filename = rand::random::<u64>().to_string();
         let filename = format!("{}.mp3", filename);
@@ -86,36 +86,77 @@ impl Minio {
             .minio_client
             .upload_object(
                 &mut UploadObjectArgs::<SseCustomerKey>::new(bucket, &filename, mp3_path)
-                    .unwrap(),
+                    .map_err(|err| format!("Invalid upload arguments: {:?}", err))?,
             )
             .await
-            .unwrap();
+            .map_err(|err| format!("Failed to upload mp3: {:?}", err))?;
         debug!("uploaded mp3: {:?}", res.location);
         Ok(format!("{}/{}/{}", self.base_url, bucket, filename))
     }
 
-    pub async fn ensure_bucket_exists(&self, bucket: &str) {
+    /// Uploads a file the assistants created, keeping its name in a folder of its own.
+    pub async fn upload_file(&self, file_path: &str, file_name: &str) -> Result<String, String> {
+        let bucket = "files";
+        self.ensure_bucket_exists(bucket).await?;
+
+        let object = format!("{}/{}", rand::random::<u64>(), object_name(file_name));
+        let res = self
+            .minio_client
+            .upload_object(
+                &mut UploadObjectArgs::<SseCustomerKey>::new(bucket, &object, file_path)
+                    .map_err(|err| format!("Invalid upload arguments: {:?}", err))?,
+            )
+            .await
+            .map_err(|err| format!("Failed to upload file: {:?}", err))?;
+        debug!("uploaded file: {:?}", res.location);
+        Ok(format!("{}/{}/{}", self.base_url, bucket, object))
+    }
+
+    pub async fn ensure_bucket_exists(&self, bucket: &str) -> Result<(), String> {
         let exists = self
             .minio_client
-            .bucket_exists(&BucketExistsArgs::new(bucket).unwrap())
+            .bucket_exists(
+                &BucketExistsArgs::new(bucket)
+                    .map_err(|err| format!("Invalid bucket name: {:?}", err))?,
+            )
             .await
-            .unwrap();
+            .map_err(|err| format!("Failed to check bucket: {:?}", err))?;
 
         if !exists {
             self.minio_client
-                .make_bucket(&MakeBucketArgs::new(bucket).unwrap())
+                .make_bucket(
+                    &MakeBucketArgs::new(bucket)
+                        .map_err(|err| format!("Invalid bucket name: {:?}", err))?,
+                )
                 .await
-                .unwrap();
+                .map_err(|err| format!("Failed to create bucket: {:?}", err))?;
 
-            self.minio_client.set_bucket_policy(
-                &SetBucketPolicyArgs::new(bucket, &download_policy())
-                    .expect("Failed to create bucket policy"),
-            ).await.expect("Failed to set bucket policy");
+            self.minio_client
+                .set_bucket_policy(
+                    &SetBucketPolicyArgs::new(bucket, &download_policy(bucket))
+                        .map_err(|err| format!("Failed to create bucket policy: {:?}", err))?,
+                )
+                .await
+                .map_err(|err| format!("Failed to set bucket policy: {:?}", err))?;
         }
+
+        Ok(())
     }
 }
 
-fn download_policy() -> String {
+/// Keeps the characters of a file name that are safe in a url.
+fn object_name(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Lets anyone download the objects of `bucket`.
+fn download_policy(bucket: &str) -> String {
     serde_json::json!({
         "Version": "2012-10-17",
         "Statement": [
@@ -131,7 +172,7 @@ fn download_policy() -> String {
                     "s3:ListBucket"
                 ],
                 "Resource": [
-                    "arn:aws:s3:::audio"
+                    format!("arn:aws:s3:::{}", bucket)
                 ]
             },
             {
@@ -145,10 +186,34 @@ fn download_policy() -> String {
                     "s3:GetObject"
                 ],
                 "Resource": [
-                    "arn:aws:s3:::audio/*"
+                    format!("arn:aws:s3:::{}/*", bucket)
                 ]
             }
         ]
     })
     .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_download_policy_uses_bucket() {
+        let policy: serde_json::Value = serde_json::from_str(&download_policy("images")).unwrap();
+        assert_eq!(policy["Statement"][0]["Resource"][0], "arn:aws:s3:::images");
+        assert_eq!(
+            policy["Statement"][1]["Resource"][0],
+            "arn:aws:s3:::images/*"
+        );
+    }
+
+    #[test]
+    fn test_object_name() {
+        assert_eq!(
+            object_name("report 2023 (final).csv"),
+            "report_2023__final_.csv"
+        );
+        assert_eq!(object_name("chart.png"), "chart.png");
+    }
+}
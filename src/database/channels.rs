@@ -1,76 +1,185 @@
 use serde::{Deserialize, Serialize};
-use sled::{open, Db, IVec};
+use sled::{Db, IVec};
 
-use crate::thread::OpenAIThread;
+use crate::{
+    thread::OpenAIThread,
+    triggers::{default_triggers, Trigger},
+};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ChannelConfiguration {
+    /// Ids of the assistants that respond in the channel, all of them when empty
     pub active_assistants: Vec<String>,
     pub thread: String,
-    pub webhook: String,
+    /// Webhook assistants post through, `None` in DMs where webhooks don't exist
+    pub webhook: Option<String>,
+    /// Assistant that answers @mentions of the bot and, with the always trigger, every
+    /// message
+    #[serde(default)]
+    pub default_assistant: Option<String>,
+    /// Rules deciding which assistants respond to a message
+    #[serde(default = "default_triggers")]
+    pub triggers: Vec<Trigger>,
+    /// Channel a Discord thread or forum post belongs to, its webhook is the parent's
+    #[serde(default)]
+    pub parent_channel: Option<u64>,
+    /// How many turns assistants may take answering each other after a human message
+    #[serde(default)]
+    pub max_rounds: u32,
+    /// Assistant that picks who speaks next in conversations between assistants
+    #[serde(default)]
+    pub moderator: Option<String>,
 }
 
-pub async fn reset_channel_thread(channel_id: u64) -> Result<(), String> {
-    let channel = get_channel(channel_id.clone())
-        .expect("Failed to fetch channel")
-        .expect("Failed to fetch channel");
-    let thread = OpenAIThread::new().await;
-    let channel_configuration = ChannelConfiguration {
-        thread: thread.id().to_string(),
-        webhook: channel.webhook,
-        active_assistants: channel.active_assistants,
-    };
-    set_channel(channel_id, &channel_configuration).expect("Failed to update channel");
-    Ok(())
-}
+impl ChannelConfiguration {
+    pub fn is_active(&self, assistant_id: &str) -> bool {
+        self.active_assistants.is_empty()
+            || self.active_assistants.iter().any(|id| id == assistant_id)
+            || self.default_assistant.as_deref() == Some(assistant_id)
+    }
+
+    /// The thread to post in through the webhook, `channel_id` itself when this is the
+    /// configuration of a Discord thread.
+    pub fn webhook_thread(&self, channel_id: u64) -> Option<u64> {
+        self.parent_channel.map(|_| channel_id)
+    }
 
-pub fn get_channel(channel: u64) -> Result<Option<ChannelConfiguration>, String> {
-    let db: Db = match open("/db/channels") {
-        Ok(db) => db,
-        Err(err) => {
-            return Err(format!("Failed to open sled database: {}", err));
+    /// Activates an assistant. An empty list, where all assistants are active, becomes the
+    /// explicit list of `all_assistants` first, so activating one doesn't switch the others
+    /// off. Either way, the list is fixed from then on: assistants created later have to be
+    /// activated in the channel.
+    pub fn activate(&mut self, assistant_id: &str, all_assistants: &[String]) {
+        if self.active_assistants.is_empty() {
+            self.active_assistants = all_assistants.to_vec();
         }
-    };
+        if !self.active_assistants.iter().any(|id| id == assistant_id) {
+            self.active_assistants.push(assistant_id.to_string());
+        }
+    }
 
-    let channel = match db.get(channel.to_string()) {
-        Ok(channel) => channel,
-        Err(_) => {
-            return Err("Failed to query db".to_string());
+    /// Deactivates an assistant. `all_assistants` is needed to turn the implicit "all
+    /// assistants are active" of an empty list into an explicit one first.
+    pub fn deactivate(&mut self, assistant_id: &str, all_assistants: &[String]) {
+        if self.active_assistants.is_empty() {
+            self.active_assistants = all_assistants.to_vec();
         }
-    };
-
-    match channel {
-        Some(channel) => {
-            let channel: ChannelConfiguration = match serde_json::from_slice(&channel) {
-                Ok(channel) => channel,
-                Err(err) => {
-                    return Err(format!("Failed to deserialize channel: {}", err));
-                }
-            };
-            Ok(Some(channel))
+        self.active_assistants.retain(|id| id != assistant_id);
+        if self.default_assistant.as_deref() == Some(assistant_id) {
+            self.default_assistant = None;
         }
-        None => Ok(None),
     }
 }
 
-pub fn set_channel(channel: u64, configuration: &ChannelConfiguration) -> Result<(), String> {
-    let db: Db = match open("/db/channels") {
-        Ok(db) => db,
-        Err(err) => {
-            return Err(format!("Failed to open sled database: {}", err));
+/// The channel configurations. The database is opened once and shared, sled only allows
+/// one open handle per path.
+#[derive(Clone)]
+pub struct ChannelStore {
+    db: Db,
+}
+
+impl ChannelStore {
+    pub fn new() -> Self {
+        let db = sled::open("/db/channels").expect("Failed to open channels database");
+        ChannelStore::from_db(db)
+    }
+
+    pub fn from_db(db: Db) -> Self {
+        ChannelStore { db }
+    }
+
+    pub fn get(&self, channel: u64) -> Result<Option<ChannelConfiguration>, String> {
+        let channel = match self.db.get(channel.to_string()) {
+            Ok(channel) => channel,
+            Err(_) => {
+                return Err("Failed to query db".to_string());
+            }
+        };
+
+        match channel {
+            Some(channel) => {
+                let channel: ChannelConfiguration = match serde_json::from_slice(&channel) {
+                    Ok(channel) => channel,
+                    Err(err) => {
+                        return Err(format!("Failed to deserialize channel: {}", err));
+                    }
+                };
+                Ok(Some(channel))
+            }
+            None => Ok(None),
         }
-    };
+    }
 
-    let channel_json = match serde_json::to_string(&configuration) {
-        Ok(channel_json) => channel_json,
-        Err(err) => {
-            return Err(format!("Failed to serialize channel: {}", err));
+    pub fn set(&self, channel: u64, configuration: &ChannelConfiguration) -> Result<(), String> {
+        let channel_json = match serde_json::to_string(&configuration) {
+            Ok(channel_json) => channel_json,
+            Err(err) => {
+                return Err(format!("Failed to serialize channel: {}", err));
+            }
+        };
+        match self
+            .db
+            .insert(channel.to_string(), IVec::from(channel_json.as_str()))
+        {
+            Ok(_) => Ok(()),
+            Err(err) => Err(format!("Failed to insert channel: {}", err)),
         }
-    };
-    match db.insert(channel.to_string(), IVec::from(channel_json.as_str())) {
-        Ok(_) => Ok(()),
-        Err(err) => {
-            return Err(format!("Failed to insert channel: {}", err));
+    }
+
+    pub async fn reset_thread(&self, channel_id: u64) -> Result<(), String> {
+        let channel = match self.get(channel_id)? {
+            Some(channel) => channel,
+            None => return Err("This channel has no thread yet".to_string()),
+        };
+        let thread = OpenAIThread::new().await;
+        let channel_configuration = ChannelConfiguration {
+            thread: thread.id().to_string(),
+            ..channel
+        };
+        self.set(channel_id, &channel_configuration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> ChannelStore {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .expect("Failed to open temporary database");
+        ChannelStore::from_db(db)
+    }
+
+    fn config(thread: &str) -> ChannelConfiguration {
+        ChannelConfiguration {
+            active_assistants: vec![],
+            thread: thread.to_string(),
+            webhook: None,
+            default_assistant: None,
+            triggers: default_triggers(),
+            parent_channel: None,
+            max_rounds: 0,
+            moderator: None,
         }
     }
+
+    #[test]
+    fn test_set_and_get_channel() {
+        let store = store();
+        assert!(store.get(1).unwrap().is_none());
+
+        store.set(1, &config("thread_1")).unwrap();
+        store.set(2, &config("thread_2")).unwrap();
+        assert_eq!(store.get(1).unwrap().unwrap().thread, "thread_1");
+        assert_eq!(store.get(2).unwrap().unwrap().thread, "thread_2");
+    }
+
+    #[test]
+    fn test_store_handles_share_the_database() {
+        let store = store();
+        let other = store.clone();
+        store.set(1, &config("thread_1")).unwrap();
+        assert_eq!(other.get(1).unwrap().unwrap().thread, "thread_1");
+    }
 }
@@ -1,116 +1,761 @@
-use serde::{Deserialize, Serialize};
-// Step 1: Import the sled crate
-use sled::open;
-use sled::{Db, IVec};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use log::error;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use sled::{Db, IVec, Tree};
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Task {
-    user_id: String,
-    title: String,
-    description: Option<String>,
-    due_date: Option<String>,
-    estimated_time: Option<String>,
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    #[default]
+    Open,
+    InProgress,
+    Done,
+    Cancelled,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ListTaskToolArguments {
+impl TaskStatus {
+    pub fn is_active(&self) -> bool {
+        matches!(self, TaskStatus::Open | TaskStatus::InProgress)
+    }
+}
+
+#[derive(
+    Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+/// Where the reminder for a task that is about to be due gets posted.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReminderTarget {
+    /// The channel the task was created in
+    #[default]
+    Channel,
+    /// A direct message to the user
+    DirectMessage,
+}
+
+/// Reminders that fail this often are given up on.
+pub const MAX_REMINDER_ATTEMPTS: u32 = 5;
+
+/// How long to wait before trying a failed reminder again, doubling with every attempt.
+fn reminder_backoff(attempts: u32) -> Duration {
+    Duration::minutes(1 << attempts.min(10))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Task {
+    #[serde(default)]
+    pub id: String,
     pub user_id: String,
+    pub title: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub status: TaskStatus,
+    #[serde(default)]
+    pub priority: TaskPriority,
+    #[serde(default, deserialize_with = "deserialize_due_date")]
+    pub due_date: Option<DateTime<Utc>>,
+    /// Free text due date of a task stored before due dates were parsed, like "next friday"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due_date_text: Option<String>,
+    pub estimated_time: Option<String>,
+    /// Unknown for tasks stored before it was recorded
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Channel and assistant the task was created from, used to send the reminder
+    #[serde(default)]
+    pub channel_id: Option<u64>,
+    #[serde(default)]
+    pub assistant_id: Option<String>,
+    #[serde(default)]
+    pub remind_via: ReminderTarget,
+    #[serde(default)]
+    pub reminded_at: Option<DateTime<Utc>>,
+    /// Failed attempts at sending the reminder, and when the last one failed
+    #[serde(default)]
+    pub reminder_attempts: u32,
+    #[serde(default)]
+    pub reminder_failed_at: Option<DateTime<Utc>>,
 }
 
-pub fn get_tasks(user_id: &str) -> Result<Vec<(String, Task)>, String> {
-    let db = match open("/db/tasks") {
-        Ok(db) => db,
-        Err(err) => {
-            return Err(format!("Failed to open sled database: {}", err));
-        }
-    };
-
-    let mut tasks: Vec<(String, Task)> = Vec::new();
-    for task in db.iter() {
-        match task {
-            Ok((key, value)) => {
-                let task: Task =
-                    serde_json::from_slice(&value).expect("Failed to deserialize task");
-                if task.user_id == *user_id {
-                    tasks.push((
-                        std::str::from_utf8(&key)
-                            .expect("Failed to convert key to string")
-                            .to_owned(),
-                        task,
-                    ));
-                }
-            }
-            Err(err) => {
-                return Err(format!("Failed to get task: {}", err));
+impl Task {
+    pub fn new(user_id: &str, title: &str) -> Self {
+        Task {
+            id: String::new(),
+            user_id: user_id.to_string(),
+            title: title.to_string(),
+            description: None,
+            status: TaskStatus::Open,
+            priority: TaskPriority::Normal,
+            due_date: None,
+            due_date_text: None,
+            estimated_time: None,
+            created_at: Some(Utc::now()),
+            completed_at: None,
+            channel_id: None,
+            assistant_id: None,
+            remind_via: ReminderTarget::Channel,
+            reminded_at: None,
+            reminder_attempts: 0,
+            reminder_failed_at: None,
+        }
+    }
+
+    /// The due date of an active task whose reminder hasn't been sent or given up on.
+    fn pending_reminder(&self) -> Option<DateTime<Utc>> {
+        self.due_date.filter(|_| {
+            self.status.is_active()
+                && self.reminded_at.is_none()
+                && self.reminder_attempts < MAX_REMINDER_ATTEMPTS
+        })
+    }
+
+    /// Whether a reminder should go out at `now` for a task due within `lead`. Failed
+    /// reminders are retried with a growing delay, up to `MAX_REMINDER_ATTEMPTS` times.
+    pub fn needs_reminder(&self, now: DateTime<Utc>, lead: Duration) -> bool {
+        let retry = self.reminder_failed_at.map_or(true, |failed_at| {
+            now - failed_at >= reminder_backoff(self.reminder_attempts)
+        });
+        match self.pending_reminder() {
+            Some(due_date) => retry && due_date - lead <= now,
+            None => false,
+        }
+    }
+
+    /// Records a failed reminder, returning whether it will be tried again.
+    pub fn reminder_failed(&mut self, now: DateTime<Utc>) -> bool {
+        self.reminder_attempts += 1;
+        self.reminder_failed_at = Some(now);
+        self.reminder_attempts < MAX_REMINDER_ATTEMPTS
+    }
+
+    /// Sets a new due date, which needs a new reminder.
+    pub fn set_due_date(&mut self, due_date: DateTime<Utc>) {
+        self.due_date = Some(due_date);
+        self.due_date_text = None;
+        self.reminded_at = None;
+        self.reminder_attempts = 0;
+        self.reminder_failed_at = None;
+    }
+
+    /// Moves the task to a new status, keeping track of when it was completed.
+    pub fn set_status(&mut self, status: TaskStatus) {
+        if status == TaskStatus::Done && self.status != TaskStatus::Done {
+            self.completed_at = Some(Utc::now());
+        } else if status != TaskStatus::Done {
+            self.completed_at = None;
+        }
+        self.status = status;
+    }
+}
+
+/// Tasks stored before due dates were parsed hold free text, keep the ones we understand.
+/// The others end up in `due_date_text` when the task is decoded from the store.
+fn deserialize_due_date<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let due_date = Option::<String>::deserialize(deserializer)?;
+    Ok(due_date.and_then(|due_date| parse_due_date(&due_date, &Utc).ok()))
+}
+
+/// Parses a due date as given by an assistant. Dates and times without an offset are
+/// interpreted in `timezone`, a date without a time is due at the end of that day.
+pub fn parse_due_date<Tz: TimeZone>(input: &str, timezone: &Tz) -> Result<DateTime<Utc>, String> {
+    let input = input.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(input) {
+        return Ok(date.with_timezone(&Utc));
+    }
+
+    let naive = [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(input, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(23, 59, 59))
+    });
+
+    match naive.map(|naive| timezone.from_local_datetime(&naive).earliest()) {
+        Some(Some(date)) => Ok(date.with_timezone(&Utc)),
+        _ => Err(format!(
+            "Invalid due date '{}', expected YYYY-MM-DD, YYYY-MM-DD HH:MM or RFC 3339",
+            input
+        )),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TaskFilter {
+    /// Statuses to include, all statuses when empty
+    pub statuses: Vec<TaskStatus>,
+    pub due_after: Option<DateTime<Utc>>,
+    pub due_before: Option<DateTime<Utc>>,
+}
+
+impl TaskFilter {
+    pub fn matches(&self, task: &Task) -> bool {
+        if !self.statuses.is_empty() && !self.statuses.contains(&task.status) {
+            return false;
+        }
+        if self.due_after.is_none() && self.due_before.is_none() {
+            return true;
+        }
+        match task.due_date {
+            Some(due_date) => {
+                self.due_after.map_or(true, |after| due_date >= after)
+                    && self.due_before.map_or(true, |before| due_date <= before)
             }
+            None => false,
         }
     }
-    Ok(tasks)
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct ListTaskToolArguments {
+    /// User name/id for which to get the tasks
+    pub user_id: String,
+    /// Only list tasks with these statuses, defaults to open and in progress tasks
+    pub status: Option<Vec<TaskStatus>>,
+    /// Only list tasks due after this date (YYYY-MM-DD, YYYY-MM-DD HH:MM or RFC 3339)
+    pub due_after: Option<String>,
+    /// Only list tasks due before this date (YYYY-MM-DD, YYYY-MM-DD HH:MM or RFC 3339)
+    pub due_before: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 pub struct CreateTaskToolArguments {
+    /// User name/id for which to create the task
     pub user_id: String,
+    /// Title of the task
     pub title: String,
+    /// Description of the task, omit if not sure
     pub description: Option<String>,
+    /// Due date of the task (YYYY-MM-DD, YYYY-MM-DD HH:MM or RFC 3339), omit this if you don't know
     pub due_date: Option<String>,
+    /// Estimated time of the task
     pub estimated_time: Option<String>,
+    /// Priority of the task, defaults to normal
+    pub priority: Option<TaskPriority>,
+    /// Where to send the reminder before the task is due, defaults to this channel
+    pub remind_via: Option<ReminderTarget>,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct CompleteTaskToolArguments {
+    /// User name/id for which to complete the task
+    pub user_id: String,
+    /// Task id
+    pub task_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct UpdateTaskToolArguments {
+    /// User name/id that owns the task
+    pub user_id: String,
+    /// Task id
+    pub task_id: String,
+    /// New status of the task
+    pub status: Option<TaskStatus>,
+    /// New priority of the task
+    pub priority: Option<TaskPriority>,
+    /// New due date of the task (YYYY-MM-DD, YYYY-MM-DD HH:MM or RFC 3339)
+    pub due_date: Option<String>,
+    /// Where to send the reminder before the task is due
+    pub remind_via: Option<ReminderTarget>,
+    /// New title of the task
+    pub title: Option<String>,
+    /// New description of the task
+    pub description: Option<String>,
+}
+
+/// Tasks keyed by id, with a `<user id>/<task id>` index so a user's tasks can be
+/// listed without scanning every task, and an index of the tasks awaiting a reminder
+/// ordered by due date.
+#[derive(Clone)]
+pub struct TaskStore {
+    tasks: Db,
+    by_user: Tree,
+    by_due_date: Tree,
 }
 
-pub fn create_task(
-    user_id: &str,
-    title: &str,
-    description: Option<String>,
-    due_date: Option<String>,
-    estimated_time: Option<String>,
-) -> Result<(), String> {
-    let db: Db = match open("/db/tasks") {
-        Ok(db) => db,
-        Err(err) => {
-            return Err(format!("Failed to open sled database: {}", err));
-        }
-    };
-
-    let task = Task {
-        user_id: user_id.to_string(),
-        title: title.to_string(),
-        description: description,
-        due_date: due_date,
-        estimated_time: estimated_time,
-    };
-
-    let id = rand::random::<u64>().to_string();
-    let task_json = match serde_json::to_string(&task) {
-        Ok(task_json) => task_json,
-        Err(err) => {
-            return Err(format!("Failed to serialize task: {}", err));
-        }
-    };
-    match db.insert(id, IVec::from(task_json.as_str())) {
-        Ok(_) => Ok(()),
-        Err(err) => {
+impl TaskStore {
+    pub fn new() -> Self {
+        let db = sled::open("/db/tasks").expect("Failed to open tasks database");
+        TaskStore::from_db(db).expect("Failed to open tasks index")
+    }
+
+    pub fn from_db(tasks: Db) -> Result<Self, String> {
+        // The due date index may be empty when no reminder is pending, its absence tells
+        // that the tasks were stored before it existed
+        let due_date_indexed = tasks
+            .tree_names()
+            .iter()
+            .any(|name| name.as_ref() == b"tasks_by_due_date");
+        let (by_user, by_due_date) = match (
+            tasks.open_tree("tasks_by_user"),
+            tasks.open_tree("tasks_by_due_date"),
+        ) {
+            (Ok(by_user), Ok(by_due_date)) => (by_user, by_due_date),
+            (Err(err), _) | (_, Err(err)) => {
+                return Err(format!("Failed to open task index: {}", err));
+            }
+        };
+
+        let store = TaskStore {
+            tasks,
+            by_user,
+            by_due_date,
+        };
+        if !store.tasks.is_empty() && (store.by_user.is_empty() || !due_date_indexed) {
+            store.reindex()?;
+        }
+        Ok(store)
+    }
+
+    fn index_key(user_id: &str, task_id: &str) -> String {
+        format!("{}/{}", user_id, task_id)
+    }
+
+    /// Due date index key of a task awaiting a reminder: the due date in seconds, flipped
+    /// to sort before and after 1970 alike, then the task id.
+    fn due_date_key(task: &Task) -> Option<Vec<u8>> {
+        task.pending_reminder().map(|due_date| {
+            let mut key = Self::due_date_prefix(due_date).to_vec();
+            key.extend_from_slice(task.id.as_bytes());
+            key
+        })
+    }
+
+    fn due_date_prefix(due_date: DateTime<Utc>) -> [u8; 8] {
+        ((due_date.timestamp() as u64) ^ (1 << 63)).to_be_bytes()
+    }
+
+    /// Builds the indexes for tasks stored before they existed.
+    fn reindex(&self) -> Result<(), String> {
+        for task in self.all()? {
+            if let Err(err) = self.index(None, &task) {
+                return Err(format!("Failed to index task: {}", err));
+            }
+        }
+        Ok(())
+    }
+
+    /// Indexes `task`, replacing the due date entry of its `previous` version.
+    fn index(&self, previous: Option<&Task>, task: &Task) -> Result<(), sled::Error> {
+        self.by_user
+            .insert(Self::index_key(&task.user_id, &task.id), IVec::default())?;
+        if let Some(key) = previous.and_then(Self::due_date_key) {
+            self.by_due_date.remove(key)?;
+        }
+        if let Some(key) = Self::due_date_key(task) {
+            self.by_due_date.insert(key, IVec::default())?;
+        }
+        Ok(())
+    }
+
+    fn decode(key: &[u8], value: &[u8]) -> Result<Task, String> {
+        let record: Value = match serde_json::from_slice(value) {
+            Ok(record) => record,
+            Err(err) => {
+                return Err(format!("Failed to deserialize task: {}", err));
+            }
+        };
+        let due_date = record["due_date"].as_str().map(str::to_owned);
+        let mut task: Task = match serde_json::from_value(record) {
+            Ok(task) => task,
+            Err(err) => {
+                return Err(format!("Failed to deserialize task: {}", err));
+            }
+        };
+        if task.due_date.is_none() && task.due_date_text.is_none() {
+            task.due_date_text = due_date;
+        }
+        task.id = String::from_utf8_lossy(key).into_owned();
+        Ok(task)
+    }
+
+    fn save(&self, task: &Task) -> Result<(), String> {
+        // An undecodable previous version was never indexed by due date
+        let previous = self.get(&task.id).unwrap_or(None);
+        let task_json = match serde_json::to_string(task) {
+            Ok(task_json) => task_json,
+            Err(err) => {
+                return Err(format!("Failed to serialize task: {}", err));
+            }
+        };
+        if let Err(err) = self
+            .tasks
+            .insert(task.id.as_str(), IVec::from(task_json.as_str()))
+        {
             return Err(format!("Failed to insert task: {}", err));
         }
+        self.index(previous.as_ref(), task)
+            .map_err(|err| format!("Failed to index task: {}", err))
     }
-}
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct CompleteTaskToolArgumetens {
-    pub id: String,
-}
+    pub fn create(&self, mut task: Task) -> Result<Task, String> {
+        task.id = rand::random::<u64>().to_string();
+        self.save(&task)?;
+        Ok(task)
+    }
+
+    pub fn update(&self, task: &Task) -> Result<(), String> {
+        if task.id.is_empty() {
+            return Err("Task has no id".to_string());
+        }
+        self.save(task)
+    }
 
-pub fn complete_task(id: &str) -> Result<(), String> {
-    let db: Db = match open("/db/tasks") {
-        Ok(db) => db,
-        Err(err) => {
-            return Err(format!("Failed to open sled database: {}", err));
+    pub fn get(&self, id: &str) -> Result<Option<Task>, String> {
+        match self.tasks.get(id) {
+            Ok(Some(task)) => Self::decode(id.as_bytes(), &task).map(Some),
+            Ok(None) => Ok(None),
+            Err(err) => Err(format!("Failed to get task: {}", err)),
         }
-    };
+    }
+
+    /// Tasks of a user matching the filter, most urgent first.
+    pub fn list(&self, user_id: &str, filter: &TaskFilter) -> Result<Vec<Task>, String> {
+        let prefix = Self::index_key(user_id, "");
+        let mut tasks = Vec::new();
+        for key in self.by_user.scan_prefix(prefix.as_bytes()).keys() {
+            let key = match key {
+                Ok(key) => key,
+                Err(err) => {
+                    return Err(format!("Failed to read task index: {}", err));
+                }
+            };
+            let task_id = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+            match self.get(&task_id) {
+                Ok(Some(task)) if task.user_id == user_id && filter.matches(&task) => {
+                    tasks.push(task)
+                }
+                Ok(_) => {}
+                Err(err) => error!("Skipping task {}: {}", task_id, err),
+            }
+        }
+
+        tasks.sort_by(|a, b| {
+            b.priority
+                .cmp(&a.priority)
+                .then_with(|| match (a.due_date, b.due_date) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => a.created_at.cmp(&b.created_at),
+                })
+        });
+        Ok(tasks)
+    }
+
+    /// Every task that can be decoded, tasks that can't are logged and skipped.
+    pub fn all(&self) -> Result<Vec<Task>, String> {
+        let mut tasks = Vec::new();
+        for task in self.tasks.iter() {
+            match task {
+                Ok((key, value)) => match Self::decode(&key, &value) {
+                    Ok(task) => tasks.push(task),
+                    Err(err) => error!("Skipping task {}: {}", String::from_utf8_lossy(&key), err),
+                },
+                Err(err) => {
+                    return Err(format!("Failed to get task: {}", err));
+                }
+            }
+        }
+        Ok(tasks)
+    }
+
+    /// Tasks that need a reminder at `now`, looked up in the due date index.
+    pub fn due_for_reminder(
+        &self,
+        now: DateTime<Utc>,
+        lead: Duration,
+    ) -> Result<Vec<Task>, String> {
+        let until = Self::due_date_prefix(now + lead + Duration::seconds(1));
+        let mut tasks = Vec::new();
+        for key in self.by_due_date.range(..until.as_slice()).keys() {
+            let key = match key {
+                Ok(key) => key,
+                Err(err) => {
+                    return Err(format!("Failed to read task index: {}", err));
+                }
+            };
+            let task_id = String::from_utf8_lossy(&key[8..]).into_owned();
+            match self.get(&task_id) {
+                Ok(Some(task)) if task.needs_reminder(now, lead) => tasks.push(task),
+                Ok(_) => {}
+                Err(err) => error!("Skipping task {}: {}", task_id, err),
+            }
+        }
+        Ok(tasks)
+    }
+
+    pub fn set_status(&self, id: &str, status: TaskStatus) -> Result<Task, String> {
+        let mut task = match self.get(id)? {
+            Some(task) => task,
+            None => {
+                return Err(format!("No task with id {}", id));
+            }
+        };
+        task.set_status(status);
+        self.update(&task)?;
+        Ok(task)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+
+    use super::*;
 
-    match db.remove(id) {
-        Ok(_) => Ok(()),
-        Err(err) => {
-            return Err(format!("Failed to delete task: {}", err));
+    fn store() -> TaskStore {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .expect("Failed to open temporary database");
+        TaskStore::from_db(db).expect("Failed to create task store")
+    }
+
+    #[test]
+    fn test_parse_due_date() {
+        assert_eq!(
+            parse_due_date("2024-01-05T10:00:00+01:00", &Utc).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 5, 9, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_due_date("2024-01-05 10:30", &Utc).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 5, 10, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_due_date("2024-01-05", &Utc).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 5, 23, 59, 59).unwrap()
+        );
+        let brussels = FixedOffset::east_opt(3600).unwrap();
+        assert_eq!(
+            parse_due_date("2024-01-05 10:30", &brussels).unwrap(),
+            Utc.with_ymd_and_hms(2024, 1, 5, 9, 30, 0).unwrap()
+        );
+        assert!(parse_due_date("next friday", &Utc).is_err());
+    }
+
+    #[test]
+    fn test_legacy_task() {
+        let task: Task = serde_json::from_str(
+            r#"{"user_id":"1","title":"Water plants","description":null,"due_date":"tomorrow","estimated_time":"5 minutes"}"#,
+        )
+        .unwrap();
+        assert_eq!(task.status, TaskStatus::Open);
+        assert_eq!(task.priority, TaskPriority::Normal);
+        assert_eq!(task.due_date, None);
+        assert_eq!(task.estimated_time.as_deref(), Some("5 minutes"));
+        assert_eq!(task.created_at, None);
+    }
+
+    #[test]
+    fn test_legacy_due_date_text_is_kept() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        db.insert(
+            "42",
+            r#"{"user_id":"1","title":"Water plants","description":null,"due_date":"next friday","estimated_time":null}"#,
+        )
+        .unwrap();
+        let store = TaskStore::from_db(db).unwrap();
+
+        let task = store.get("42").unwrap().unwrap();
+        assert_eq!(task.due_date, None);
+        assert_eq!(task.due_date_text.as_deref(), Some("next friday"));
+
+        store.update(&task).unwrap();
+        let task = store.get("42").unwrap().unwrap();
+        assert_eq!(task.due_date_text.as_deref(), Some("next friday"));
+    }
+
+    #[test]
+    fn test_undecodable_task_is_skipped() {
+        let store = store();
+        store.create(Task::new("1", "Water plants")).unwrap();
+        store.tasks.insert("13", "not a task").unwrap();
+        store
+            .by_user
+            .insert(TaskStore::index_key("1", "13"), IVec::default())
+            .unwrap();
+
+        assert_eq!(store.all().unwrap().len(), 1);
+        assert_eq!(store.list("1", &TaskFilter::default()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_list_by_user() {
+        let store = store();
+        store.create(Task::new("1", "Water plants")).unwrap();
+        store.create(Task::new("2", "Feed cat")).unwrap();
+        let mut urgent = Task::new("1", "Pay rent");
+        urgent.priority = TaskPriority::Urgent;
+        store.create(urgent).unwrap();
+
+        let tasks = store.list("1", &TaskFilter::default()).unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].title, "Pay rent");
+        assert_eq!(store.list("2", &TaskFilter::default()).unwrap().len(), 1);
+        assert!(store.list("12", &TaskFilter::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_complete_keeps_history() {
+        let store = store();
+        let task = store.create(Task::new("1", "Water plants")).unwrap();
+
+        let done = store.set_status(&task.id, TaskStatus::Done).unwrap();
+        assert!(done.completed_at.is_some());
+
+        let stored = store.get(&task.id).unwrap().unwrap();
+        assert_eq!(stored.status, TaskStatus::Done);
+        assert_eq!(stored.completed_at, done.completed_at);
+
+        let open = TaskFilter {
+            statuses: vec![TaskStatus::Open, TaskStatus::InProgress],
+            ..Default::default()
+        };
+        assert!(store.list("1", &open).unwrap().is_empty());
+
+        let reopened = store.set_status(&task.id, TaskStatus::Open).unwrap();
+        assert_eq!(reopened.completed_at, None);
+    }
+
+    #[test]
+    fn test_due_window() {
+        let store = store();
+        let now = Utc::now();
+        let mut soon = Task::new("1", "Soon");
+        soon.due_date = Some(now + Duration::hours(2));
+        store.create(soon).unwrap();
+        let mut later = Task::new("1", "Later");
+        later.due_date = Some(now + Duration::days(7));
+        store.create(later).unwrap();
+        store.create(Task::new("1", "Whenever")).unwrap();
+
+        let filter = TaskFilter {
+            due_before: Some(now + Duration::days(1)),
+            ..Default::default()
+        };
+        let tasks = store.list("1", &filter).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].title, "Soon");
+    }
+
+    #[test]
+    fn test_due_for_reminder() {
+        let store = store();
+        let now = Utc::now();
+        let mut soon = Task::new("1", "Soon");
+        soon.due_date = Some(now + Duration::minutes(30));
+        let soon = store.create(soon).unwrap();
+        let mut later = Task::new("1", "Later");
+        later.due_date = Some(now + Duration::days(2));
+        store.create(later).unwrap();
+        let mut done = Task::new("1", "Done");
+        done.due_date = Some(now + Duration::minutes(10));
+        done.set_status(TaskStatus::Done);
+        store.create(done).unwrap();
+
+        let due = store.due_for_reminder(now, Duration::hours(1)).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, soon.id);
+
+        let mut reminded = due[0].clone();
+        reminded.reminded_at = Some(now);
+        store.update(&reminded).unwrap();
+        assert!(store
+            .due_for_reminder(now, Duration::hours(1))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_due_date_index_follows_updates() {
+        let store = store();
+        let now = Utc::now();
+        let lead = Duration::hours(1);
+        let mut task = Task::new("1", "Moving target");
+        task.due_date = Some(now + Duration::days(3));
+        let mut task = store.create(task).unwrap();
+        assert!(store.due_for_reminder(now, lead).unwrap().is_empty());
+
+        task.set_due_date(now + Duration::minutes(30));
+        store.update(&task).unwrap();
+        assert_eq!(store.due_for_reminder(now, lead).unwrap().len(), 1);
+
+        task.set_status(TaskStatus::Cancelled);
+        store.update(&task).unwrap();
+        assert!(store.due_for_reminder(now, lead).unwrap().is_empty());
+        assert!(store.by_due_date.is_empty());
+    }
+
+    #[test]
+    fn test_failed_reminders_back_off_and_give_up() {
+        let now = Utc::now();
+        let lead = Duration::hours(1);
+        let mut task = Task::new("1", "Soon");
+        task.due_date = Some(now + Duration::minutes(30));
+
+        assert!(task.reminder_failed(now));
+        assert!(!task.needs_reminder(now, lead));
+        assert!(task.needs_reminder(now + Duration::minutes(2), lead));
+
+        for _ in 1..MAX_REMINDER_ATTEMPTS - 1 {
+            assert!(task.reminder_failed(now));
         }
+        assert!(!task.reminder_failed(now));
+        assert!(!task.needs_reminder(now + Duration::days(1), lead));
+
+        task.set_due_date(now + Duration::days(2));
+        assert!(task.needs_reminder(now + Duration::days(2), lead));
+    }
+
+    #[test]
+    fn test_reindex_legacy_tasks() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        db.insert(
+            "42",
+            r#"{"user_id":"1","title":"Old task","description":null,"due_date":null,"estimated_time":null}"#,
+        )
+        .unwrap();
+
+        db.insert(
+            "43",
+            r#"{"user_id":"1","title":"Old deadline","description":null,"due_date":"2024-01-05","estimated_time":null}"#,
+        )
+        .unwrap();
+
+        let store = TaskStore::from_db(db).unwrap();
+        let tasks = store.list("1", &TaskFilter::default()).unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].id, "43");
+        assert_eq!(tasks[1].id, "42");
+
+        let due = store
+            .due_for_reminder(Utc::now(), Duration::hours(1))
+            .unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, "43");
     }
 }
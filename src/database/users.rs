@@ -1,11 +1,15 @@
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct User {
     pub id: String,
     pub name: String,
     pub nickname: Option<String>,
     pub prefered_name: Option<String>,
+    /// IANA timezone name, e.g. `Europe/Brussels`
+    #[serde(default)]
+    pub timezone: Option<String>,
 }
 
 impl User {
@@ -20,9 +24,17 @@ impl User {
             name,
             nickname,
             prefered_name,
+            timezone: None,
         }
     }
 
+    /// The user's timezone, if they registered a valid one.
+    pub fn tz(&self) -> Option<Tz> {
+        self.timezone
+            .as_ref()
+            .and_then(|timezone| timezone.parse::<Tz>().ok())
+    }
+
     pub fn get_name(&self) -> String {
         match &self.prefered_name {
             Some(name) => name.to_owned(),
@@ -41,6 +53,10 @@ pub struct UserStore {
 impl UserStore {
     pub fn new() -> Self {
         let db = sled::open("/db/users").expect("Failed to open users database");
+        UserStore::from_db(db)
+    }
+
+    pub fn from_db(db: sled::Db) -> Self {
         UserStore { db }
     }
 
@@ -58,7 +74,42 @@ impl UserStore {
         }
     }
 
+    /// Looks a user up by Discord id, mention (`<@id>`) or any of their names.
+    pub fn find_user(&self, name_or_id: &str) -> Option<User> {
+        let name_or_id = name_or_id.trim();
+        let id = name_or_id
+            .trim_start_matches("<@")
+            .trim_start_matches('!')
+            .trim_end_matches('>');
+        if let Some(user) = self.get_user(id) {
+            return Some(user);
+        }
+
+        let name = name_or_id.trim_start_matches('@').to_lowercase();
+        self.db
+            .iter()
+            .values()
+            .filter_map(|user| user.ok())
+            .filter_map(|user| serde_json::from_slice::<User>(&user).ok())
+            .find(|user| {
+                user.name.to_lowercase() == name
+                    || user.get_name().to_lowercase() == name
+                    || user
+                        .nickname
+                        .as_ref()
+                        .is_some_and(|nickname| nickname.to_lowercase() == name)
+            })
+    }
+
+    /// Stores `user`, keeping the preferred name and timezone of an earlier registration
+    /// when the new one doesn't set them.
     pub fn register_user(&self, user: &User) -> Result<(), String> {
+        let mut user = user.clone();
+        if let Some(existing) = self.get_user(&user.id) {
+            user.prefered_name = user.prefered_name.or(existing.prefered_name);
+            user.timezone = user.timezone.or(existing.timezone);
+        }
+
         let user_json = match serde_json::to_string(&user) {
             Ok(user_json) => user_json,
             Err(err) => {
@@ -71,3 +122,46 @@ impl UserStore {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> UserStore {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .expect("Failed to open temporary database");
+        UserStore::from_db(db)
+    }
+
+    #[test]
+    fn test_register_keeps_timezone() {
+        let store = store();
+        let mut user = User::new("1".to_string(), "ada".to_string(), None, None);
+        user.timezone = Some("Europe/Brussels".to_string());
+        store.register_user(&user).unwrap();
+
+        store
+            .register_user(&User::new(
+                "1".to_string(),
+                "ada".to_string(),
+                Some("Ada".to_string()),
+                None,
+            ))
+            .unwrap();
+
+        let user = store.get_user("1").unwrap();
+        assert_eq!(user.nickname.as_deref(), Some("Ada"));
+        assert_eq!(user.tz(), Some(chrono_tz::Europe::Brussels));
+    }
+
+    #[test]
+    fn test_legacy_user_without_timezone() {
+        let user: User =
+            serde_json::from_str(r#"{"id":"1","name":"ada","nickname":null,"prefered_name":null}"#)
+                .unwrap();
+        assert_eq!(user.timezone, None);
+        assert_eq!(user.tz(), None);
+    }
+}
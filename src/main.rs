@@ -1,11 +1,17 @@
-#![feature(variant_count)]
-
+mod annotations;
 mod bot;
 mod commands;
+mod config;
+mod conversation;
 mod database;
 mod openai;
+mod reminders;
+mod streaming;
+#[cfg(test)]
+mod test_support;
 mod thread;
 mod tools;
+mod triggers;
 
 use bot::Bot;
 use std::env;
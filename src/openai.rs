@@ -1,11 +1,13 @@
 use async_openai::{
-    config::OpenAIConfig,
+    config::{Config, OpenAIConfig},
     error::OpenAIError,
     types::{
-        AssistantObject, AssistantTools, CreateImageRequestArgs, CreateSpeechRequestArgs,
-        CreateSpeechResponse, CreateTranscriptionRequestArgs, CreateTranscriptionResponse,
-        ImageModel, ImageQuality, ImageSize, ImageStyle, ImagesResponse, ModifyAssistantRequest,
-        ResponseFormat, SpeechModel, Voice,
+        AssistantObject, AssistantTools, ChatCompletionRequestMessage,
+        ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+        CreateAssistantRequestArgs, CreateChatCompletionRequestArgs, CreateImageRequestArgs,
+        CreateSpeechRequestArgs, CreateSpeechResponse, CreateTranscriptionRequestArgs,
+        CreateTranscriptionResponse, ImageModel, ImageQuality, ImageSize, ImageStyle,
+        ImagesResponse, ModifyAssistantRequest, ResponseFormat, SpeechModel, Voice,
     },
     Client,
 };
@@ -15,10 +17,15 @@ use serenity::client::Context;
 use std::{
     collections::HashMap,
     fmt,
+    future::Future,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
+    sync::Arc,
+    time::{Duration, Instant},
 };
+use tokio::sync::Mutex;
 
-use crate::thread::OpenAIThread;
+use crate::{config::env_or, thread::OpenAIThread};
 
 #[derive(Debug)]
 pub struct Assistant {
@@ -29,8 +36,10 @@ pub struct Assistant {
     pub author_id: String,
 }
 
+/// The OpenAI threads in use. The store is shared by all channels, so it is only locked to
+/// look threads up, each thread serializes its own runs.
 pub struct ThreadStore {
-    threads: HashMap<String, OpenAIThread>,
+    threads: HashMap<String, Arc<OpenAIThread>>,
 }
 
 impl ThreadStore {
@@ -40,16 +49,60 @@ impl ThreadStore {
         }
     }
 
-    pub fn get(
-        &mut self,
-        id: &str
-    ) -> Option<&OpenAIThread> {
-        self.threads.get(id)
+    pub fn get(&self, id: &str) -> Option<Arc<OpenAIThread>> {
+        self.threads.get(id).cloned()
     }
 
     pub fn add_thread(&mut self, thread: OpenAIThread) {
         debug!("registring new thread in store");
-        self.threads.insert(thread.id().to_owned(), thread);
+        self.threads
+            .insert(thread.id().to_owned(), Arc::new(thread));
+    }
+}
+
+/// Shared list of assistants, so messages and commands don't page through the whole
+/// list on every use. Commands that change an assistant invalidate it.
+pub struct AssistantCache {
+    ttl: Duration,
+    entry: Mutex<Option<(Instant, Arc<Vec<AssistantObject>>)>>,
+}
+
+impl AssistantCache {
+    pub fn new(ttl: Duration) -> Self {
+        AssistantCache {
+            ttl,
+            entry: Mutex::new(None),
+        }
+    }
+
+    pub async fn assistants(
+        &self,
+        openai: &OpenAI,
+    ) -> Result<Arc<Vec<AssistantObject>>, OpenAIError> {
+        self.get_or_fetch(|| openai.assistants()).await
+    }
+
+    pub async fn invalidate(&self) {
+        debug!("invalidating assistant cache");
+        *self.entry.lock().await = None;
+    }
+
+    async fn get_or_fetch<F, Fut>(&self, fetch: F) -> Result<Arc<Vec<AssistantObject>>, OpenAIError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<AssistantObject>, OpenAIError>>,
+    {
+        // Holding the lock while fetching makes concurrent callers wait for one request
+        let mut entry = self.entry.lock().await;
+        if let Some((fetched_at, assistants)) = entry.as_ref() {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(assistants.clone());
+            }
+        }
+
+        let assistants = Arc::new(fetch().await?);
+        *entry = Some((Instant::now(), assistants.clone()));
+        Ok(assistants)
     }
 }
 
@@ -70,36 +123,70 @@ fn voice_to_string(voice: &Voice) -> String {
     }
 }
 
+/// The maximum page size of the assistants list endpoint.
+const ASSISTANTS_PAGE_SIZE: usize = 100;
+
+const SUMMARY_PROMPT: &str = "Summarize the following Discord conversation in a few \
+short paragraphs. Keep the decisions, open questions and who said what, lines start with \
+the id of the user or with assistant.";
+
 impl OpenAI {
     pub fn new() -> Self {
         let client: Client<OpenAIConfig> = Client::new();
         OpenAI { client }
     }
 
-    pub async fn assistants(&self) -> Vec<AssistantObject> {
-        let query = [("limit", "100")];
-        let response = self
-            .client
-            .assistants()
-            .list(&query)
-            .await
-            .expect("Failed to list assistants");
-        let mut assistants = response.data;
-        while response.has_more {
-            let query = [
-                ("limit", "100"),
-                ("after", assistants.last().unwrap().id.as_str()),
-            ];
-            let response = self
-                .client
-                .assistants()
-                .list(&query)
-                .await
-                .expect("Failed to list assistants");
+    pub fn with_config(config: OpenAIConfig) -> Self {
+        OpenAI {
+            client: Client::with_config(config),
+        }
+    }
+
+    /// Lists all assistants, following the `after` cursor until the API reports there
+    /// are no more pages.
+    pub async fn assistants(&self) -> Result<Vec<AssistantObject>, OpenAIError> {
+        let mut assistants: Vec<AssistantObject> = Vec::new();
+        loop {
+            let mut query = vec![("limit", ASSISTANTS_PAGE_SIZE.to_string())];
+            if let Some(last) = assistants.last() {
+                query.push(("after", last.id.clone()));
+            }
+
+            let response = self.client.assistants().list(&query).await?;
+            let has_more = response.has_more && !response.data.is_empty();
             assistants.extend(response.data);
+            if !has_more {
+                return Ok(assistants);
+            }
         }
+    }
 
-        assistants
+    pub async fn create_assistant(
+        &self,
+        name: &str,
+        description: &str,
+        instructions: &str,
+        model: &str,
+        tools: Vec<AssistantTools>,
+        avatar: Option<&str>,
+    ) -> Result<AssistantObject, OpenAIError> {
+        let mut metadata = HashMap::new();
+        if let Some(avatar) = avatar {
+            metadata.insert(
+                "avatar".to_string(),
+                serde_json::Value::String(avatar.to_string()),
+            );
+        }
+
+        let request = CreateAssistantRequestArgs::default()
+            .name(name)
+            .description(description)
+            .instructions(instructions)
+            .model(model)
+            .tools(tools)
+            .metadata(metadata)
+            .build()?;
+        self.client.assistants().create(request).await
     }
 
     pub async fn set_assistant_image(
@@ -145,6 +232,63 @@ impl OpenAI {
         Ok(())
     }
 
+    pub async fn assistant(&self, id: &str) -> Result<AssistantObject, OpenAIError> {
+        self.client.assistants().retrieve(id).await
+    }
+
+    pub async fn update_assistant(
+        &self,
+        assistant: &AssistantObject,
+        name: &str,
+        description: &str,
+        instructions: &str,
+        model: &str,
+    ) -> Result<AssistantObject, OpenAIError> {
+        self.client
+            .assistants()
+            .update(
+                &assistant.id,
+                ModifyAssistantRequest {
+                    model: model.to_string(),
+                    name: Some(name.to_string()),
+                    description: Some(description.to_string()),
+                    instructions: Some(instructions.to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+    }
+
+    pub async fn delete_assistant(&self, assistant: &AssistantObject) -> Result<(), OpenAIError> {
+        self.client.assistants().delete(&assistant.id).await?;
+        Ok(())
+    }
+
+    /// Creates a copy of `assistant` with the same model, instructions, tools, files and
+    /// metadata under a new name.
+    pub async fn clone_assistant(
+        &self,
+        assistant: &AssistantObject,
+        name: &str,
+    ) -> Result<AssistantObject, OpenAIError> {
+        let mut request = CreateAssistantRequestArgs::default();
+        request
+            .name(name)
+            .model(assistant.model.clone())
+            .tools(assistant.tools.clone())
+            .file_ids(assistant.file_ids.clone());
+        if let Some(description) = &assistant.description {
+            request.description(description);
+        }
+        if let Some(instructions) = &assistant.instructions {
+            request.instructions(instructions);
+        }
+        if let Some(metadata) = &assistant.metadata {
+            request.metadata(metadata.clone());
+        }
+        self.client.assistants().create(request.build()?).await
+    }
+
     pub async fn generate_image(
         &self,
         prompt: &str,
@@ -169,6 +313,81 @@ impl OpenAI {
         self.client.images().create(request).await
     }
 
+    /// Summarizes a conversation transcript with a chat completion, the model is
+    /// configurable through `SUMMARY_MODEL`.
+    pub async fn summarize(&self, transcript: &str) -> Result<String, OpenAIError> {
+        let model = env_or("SUMMARY_MODEL", "gpt-3.5-turbo-1106".to_string());
+        self.complete(&model, SUMMARY_PROMPT, transcript).await
+    }
+
+    /// Answers a single user message with a chat completion.
+    pub async fn complete(
+        &self,
+        model: &str,
+        system: &str,
+        user: &str,
+    ) -> Result<String, OpenAIError> {
+        let messages: Vec<ChatCompletionRequestMessage> = vec![
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(system)
+                .build()?
+                .into(),
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(user.to_owned())
+                .build()?
+                .into(),
+        ];
+        let request = CreateChatCompletionRequestArgs::default()
+            .model(model)
+            .messages(messages)
+            .build()?;
+
+        let response = self.client.chat().create(request).await?;
+        Ok(response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .unwrap_or_default())
+    }
+
+    /// The name a file was uploaded or created with, without the sandbox directory.
+    pub async fn file_name(&self, file_id: &str) -> Result<String, OpenAIError> {
+        let file = self.client.files().retrieve(file_id).await?;
+        Ok(Path::new(&file.filename)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or(file.filename))
+    }
+
+    /// Downloads a file the assistants created, like a code interpreter chart, into `dir`.
+    pub async fn save_file(
+        &self,
+        file_id: &str,
+        file_name: &str,
+        dir: &str,
+    ) -> Result<PathBuf, OpenAIError> {
+        let config = self.client.config();
+        let content = reqwest::Client::new()
+            .get(config.url(&format!("/files/{}/content", file_id)))
+            .headers(config.headers())
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        let path = Path::new(dir).join(file_name);
+        let saved = async {
+            tokio::fs::create_dir_all(dir).await?;
+            tokio::fs::write(&path, content).await
+        };
+        saved
+            .await
+            .map_err(|err| OpenAIError::FileSaveError(format!("{}: {}", path.display(), err)))?;
+        Ok(path)
+    }
+
     pub async fn tts(
         &self,
         prompt: &str,
@@ -210,7 +429,7 @@ impl OpenAI {
             .arg(format!("{file_name}.webm"))
             .arg(url)
             .output()
-            .expect("failed to execute process");
+            .map_err(|err| format!("Failed to run yt-dlp: {}", err))?;
 
         if !file.status.success() {
             error!("yt-dlp failed: {:?}", file);
@@ -223,7 +442,7 @@ impl OpenAI {
             .arg("--file")
             .arg(format!("{file_name}.webm"))
             .output()
-            .expect("failed to execute process");
+            .map_err(|err| format!("Failed to run whisper: {}", err))?;
 
         if !transcript.status.success() {
             error!("whisper failed: {:?}", transcript);
@@ -234,3 +453,191 @@ impl OpenAI {
         Ok(result.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, Request, Respond, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::test_support::assistant_json;
+
+    /// Serves `total` assistants named `asst_<index>` from the list endpoint, paging on
+    /// the `limit` and `after` query parameters like the real API.
+    struct AssistantPages {
+        total: usize,
+    }
+
+    impl Respond for AssistantPages {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let param = |name: &str| {
+                request
+                    .url
+                    .query_pairs()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+            };
+            let limit = param("limit")
+                .and_then(|limit| limit.parse::<usize>().ok())
+                .unwrap_or(20);
+            let start = param("after")
+                .and_then(|after| after.trim_start_matches("asst_").parse::<usize>().ok())
+                .map_or(0, |after| after + 1);
+            let end = (start + limit).min(self.total);
+
+            let data = (start..end)
+                .map(|index| {
+                    assistant_json(&format!("asst_{}", index), &format!("Assistant {}", index))
+                })
+                .collect::<Vec<_>>();
+
+            ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": data,
+                "first_id": data.first().map(|assistant| assistant["id"].clone()),
+                "last_id": data.last().map(|assistant| assistant["id"].clone()),
+                "has_more": end < self.total,
+            }))
+        }
+    }
+
+    async fn list_assistants(total: usize, expected_requests: u64) -> Vec<AssistantObject> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/assistants"))
+            .respond_with(AssistantPages { total })
+            .expect(expected_requests)
+            .mount(&server)
+            .await;
+
+        let openai = OpenAI::with_config(
+            OpenAIConfig::new()
+                .with_api_base(server.uri())
+                .with_api_key("test"),
+        );
+        openai
+            .assistants()
+            .await
+            .expect("Failed to list assistants")
+    }
+
+    fn assert_all_listed(assistants: &[AssistantObject], total: usize) {
+        assert_eq!(assistants.len(), total);
+        for (index, assistant) in assistants.iter().enumerate() {
+            assert_eq!(assistant.id, format!("asst_{}", index));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_assistants_empty() {
+        let assistants = list_assistants(0, 1).await;
+        assert_all_listed(&assistants, 0);
+    }
+
+    #[tokio::test]
+    async fn test_assistants_single_page() {
+        let assistants = list_assistants(100, 1).await;
+        assert_all_listed(&assistants, 100);
+    }
+
+    #[tokio::test]
+    async fn test_assistants_multiple_pages() {
+        let assistants = list_assistants(250, 3).await;
+        assert_all_listed(&assistants, 250);
+    }
+
+    #[tokio::test]
+    async fn test_save_file() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/files/file_chart/content"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0x89, 0x50, 0x4e, 0x47]))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let openai = OpenAI::with_config(
+            OpenAIConfig::new()
+                .with_api_base(server.uri())
+                .with_api_key("test"),
+        );
+        let dir = std::env::temp_dir().join("cognicompany-test-save-file");
+        let saved = openai
+            .save_file("file_chart", "chart.png", &dir.display().to_string())
+            .await
+            .unwrap();
+        assert_eq!(saved, dir.join("chart.png"));
+        assert_eq!(
+            tokio::fs::read(&saved).await.unwrap(),
+            vec![0x89, 0x50, 0x4e, 0x47]
+        );
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert!(openai
+            .save_file("missing", "missing.png", "./unused")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_assistants_api_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/assistants"))
+            .respond_with(ResponseTemplate::new(500).set_body_json(json!({
+                "error": {
+                    "message": "Internal error",
+                    "type": "server_error",
+                    "param": null,
+                    "code": null,
+                }
+            })))
+            .mount(&server)
+            .await;
+
+        let openai = OpenAI::with_config(
+            OpenAIConfig::new()
+                .with_api_base(server.uri())
+                .with_api_key("test"),
+        );
+        assert!(openai.assistants().await.is_err());
+    }
+
+    async fn fetch_counted(cache: &AssistantCache, fetches: &AtomicUsize) {
+        cache
+            .get_or_fetch(|| async {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                Ok(vec![])
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_assistant_cache_reuses_entry() {
+        let cache = AssistantCache::new(Duration::from_secs(60));
+        let fetches = AtomicUsize::new(0);
+
+        fetch_counted(&cache, &fetches).await;
+        fetch_counted(&cache, &fetches).await;
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        cache.invalidate().await;
+        fetch_counted(&cache, &fetches).await;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_assistant_cache_expires() {
+        let cache = AssistantCache::new(Duration::ZERO);
+        let fetches = AtomicUsize::new(0);
+
+        fetch_counted(&cache, &fetches).await;
+        fetch_counted(&cache, &fetches).await;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,186 @@
+use async_openai::types::AssistantObject;
+use chrono::{Duration, Utc};
+use log::{debug, error};
+use serenity::{
+    builder::CreateMessage,
+    client::Context,
+    model::id::{ChannelId, UserId},
+};
+
+use crate::{
+    bot::{assistant_avatar, persona_embed, persona_say},
+    config::env_or,
+    database::{
+        channels::ChannelStore,
+        tasks::{ReminderTarget, Task, TaskStore, MAX_REMINDER_ATTEMPTS},
+    },
+    openai::{AssistantCache, OpenAI},
+};
+
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How long before the due date a reminder is sent, configurable through
+/// `REMINDER_LEAD_MINUTES`.
+fn reminder_lead() -> Duration {
+    Duration::minutes(env_or("REMINDER_LEAD_MINUTES", 60))
+}
+
+/// Periodically scans the task database and reminds users of tasks that are about to
+/// be due. Runs for the lifetime of the bot.
+pub async fn run(ctx: Context) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(err) = send_due_reminders(&ctx).await {
+            error!("Failed to send task reminders: {}", err);
+        }
+    }
+}
+
+async fn send_due_reminders(ctx: &Context) -> Result<(), String> {
+    let (store, assistants) = {
+        let data_read = ctx.data.read().await;
+        let store = data_read
+            .get::<TaskStore>()
+            .expect("Expected TaskStore in TypeMap")
+            .clone();
+        let openai = data_read
+            .get::<OpenAI>()
+            .expect("Expected OpenAI in TypeMap");
+        let assistants = data_read
+            .get::<AssistantCache>()
+            .expect("Expected AssistantCache in TypeMap")
+            .assistants(openai)
+            .await
+            .map_err(|err| format!("Failed to list assistants: {}", err))?;
+        (store, assistants)
+    };
+
+    let now = Utc::now();
+    for mut task in store.due_for_reminder(now, reminder_lead())? {
+        debug!("Sending reminder for task {}", task.id);
+        let assistant = task.assistant_id.as_ref().and_then(|assistant_id| {
+            assistants
+                .iter()
+                .find(|assistant| &assistant.id == assistant_id)
+        });
+
+        match remind(ctx, &task, assistant).await {
+            Ok(()) => task.reminded_at = Some(now),
+            Err(err) => {
+                error!("Failed to send reminder for task {}: {}", task.id, err);
+                if !task.reminder_failed(now) {
+                    error!(
+                        "Giving up on the reminder for task {} after {} attempts",
+                        task.id, MAX_REMINDER_ATTEMPTS
+                    );
+                }
+            }
+        }
+        store.update(&task)?;
+    }
+    Ok(())
+}
+
+fn reminder_text(task: &Task) -> String {
+    let due = match task.due_date {
+        Some(due_date) => format!("is due <t:{}:R>", due_date.timestamp()),
+        None => "is due soon".to_string(),
+    };
+    format!("<@{}> reminder: **{}** {}", task.user_id, task.title, due)
+}
+
+/// Posts the reminder as the assistant. Mentions in an embed don't notify anyone, so
+/// without a webhook the user is mentioned in the content of the message as well.
+async fn post_reminder(
+    ctx: &Context,
+    channel_id: ChannelId,
+    webhook: Option<&str>,
+    thread_id: Option<ChannelId>,
+    task: &Task,
+    avatar: Option<&str>,
+    name: Option<&str>,
+) -> Result<(), serenity::Error> {
+    let message = reminder_text(task);
+    if webhook.is_some() {
+        return persona_say(
+            ctx,
+            channel_id,
+            webhook,
+            thread_id,
+            &message,
+            vec![],
+            avatar,
+            name,
+        )
+        .await;
+    }
+
+    channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(format!("<@{}>", task.user_id))
+                .embed(persona_embed(&message, avatar, name)),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn remind(
+    ctx: &Context,
+    task: &Task,
+    assistant: Option<&AssistantObject>,
+) -> Result<(), String> {
+    let name = assistant.and_then(|assistant| assistant.name.as_deref());
+    let avatar = assistant.and_then(assistant_avatar);
+
+    if task.remind_via == ReminderTarget::Channel {
+        let channel = match task.channel_id {
+            Some(channel_id) => {
+                let data = ctx.data.read().await;
+                data.get::<ChannelStore>()
+                    .expect("Expected ChannelStore in TypeMap")
+                    .get(channel_id)?
+                    .map(|channel| (channel_id, channel))
+            }
+            None => None,
+        };
+        if let Some((channel_id, channel)) = channel {
+            return post_reminder(
+                ctx,
+                ChannelId::new(channel_id),
+                channel.webhook.as_deref(),
+                channel.webhook_thread(channel_id).map(ChannelId::new),
+                task,
+                avatar,
+                name,
+            )
+            .await
+            .map_err(|err| format!("Failed to post reminder: {}", err));
+        }
+        debug!("Task {} has no channel, sending reminder as DM", task.id);
+    }
+
+    let user_id = task
+        .user_id
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid user id {}", task.user_id))?;
+    let dm = UserId::new(user_id)
+        .create_dm_channel(&ctx.http)
+        .await
+        .map_err(|err| format!("Failed to open DM channel: {}", err))?;
+
+    post_reminder(
+        ctx,
+        dm.id,
+        None,
+        None,
+        task,
+        avatar,
+        Some(name.unwrap_or("Reminder")),
+    )
+    .await
+    .map_err(|err| format!("Failed to send DM: {}", err))?;
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use async_openai::types::RunToolCallObject;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// One server-sent event of a streamed run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    pub event: String,
+    pub data: String,
+}
+
+/// Splits a server-sent event stream into events. Chunks can end anywhere, also in the
+/// middle of a line or a UTF-8 character.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Feeds the next chunk of the response, returning the events it completed.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = vec![];
+        while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line = self.buffer.drain(..=end).collect::<Vec<u8>>();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                if let Some(event) = self.dispatch() {
+                    events.push(event);
+                }
+                continue;
+            }
+
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line, ""),
+            };
+            match field {
+                "event" => self.event = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+        events
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        let data = self.data.drain(..).collect::<Vec<String>>().join("\n");
+        Some(SseEvent {
+            event: event.unwrap_or_else(|| "message".to_string()),
+            data,
+        })
+    }
+}
+
+/// What happened in a streamed run, as far as the bot cares.
+#[derive(Debug, Clone)]
+pub enum RunEvent {
+    Created {
+        run_id: String,
+    },
+    /// A piece of the text the assistant is writing
+    TextDelta(String),
+    /// The run waits for the output of these tool calls
+    RequiresAction(Vec<RunToolCallObject>),
+    Completed,
+    /// The run failed, was cancelled or expired
+    Ended(String),
+    /// The stream is over
+    Done,
+    Other,
+}
+
+#[derive(Deserialize)]
+struct RequiredAction {
+    submit_tool_outputs: SubmitToolOutputs,
+}
+
+#[derive(Deserialize)]
+struct SubmitToolOutputs {
+    tool_calls: Vec<RunToolCallObject>,
+}
+
+fn text_deltas(data: &Value) -> String {
+    data["delta"]["content"]
+        .as_array()
+        .map(|content| {
+            content
+                .iter()
+                .filter(|part| part["type"] == "text")
+                .filter_map(|part| part["text"]["value"].as_str())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Interprets an event of the runs API.
+pub fn run_event(event: &SseEvent) -> Result<RunEvent, String> {
+    if event.event == "done" || event.data == "[DONE]" {
+        return Ok(RunEvent::Done);
+    }
+    let data: Value = serde_json::from_str(&event.data)
+        .map_err(|err| format!("Invalid {} event: {}", event.event, err))?;
+
+    let run_event = match event.event.as_str() {
+        "thread.run.created" => RunEvent::Created {
+            run_id: data["id"].as_str().unwrap_or_default().to_string(),
+        },
+        "thread.message.delta" => RunEvent::TextDelta(text_deltas(&data)),
+        "thread.run.requires_action" => {
+            let action: RequiredAction = serde_json::from_value(data["required_action"].clone())
+                .map_err(|err| format!("Invalid required action: {}", err))?;
+            RunEvent::RequiresAction(action.submit_tool_outputs.tool_calls)
+        }
+        "thread.run.completed" => RunEvent::Completed,
+        "thread.run.failed" => RunEvent::Ended(format!(
+            "Run failed: {}",
+            data["last_error"]["message"]
+                .as_str()
+                .unwrap_or("unknown error")
+        )),
+        "thread.run.cancelled" => RunEvent::Ended("Run was cancelled".to_string()),
+        "thread.run.expired" => RunEvent::Ended("Run expired".to_string()),
+        "error" => RunEvent::Ended(format!(
+            "Stream error: {}",
+            data["message"].as_str().unwrap_or(&event.data)
+        )),
+        _ => RunEvent::Other,
+    };
+    Ok(run_event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parser_handles_split_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser.feed(b"event: thread.message.delta\nda").is_empty());
+
+        let events = parser.feed(b"ta: {\"a\":1}\n\nevent: done\r\ndata: [DONE]\r\n\r\n");
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: "thread.message.delta".to_string(),
+                    data: "{\"a\":1}".to_string(),
+                },
+                SseEvent {
+                    event: "done".to_string(),
+                    data: "[DONE]".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parser_handles_split_characters() {
+        let mut parser = SseParser::default();
+        let bytes = "data: héllo\n\n".as_bytes();
+        assert!(parser.feed(&bytes[..8]).is_empty());
+        let events = parser.feed(&bytes[8..]);
+        assert_eq!(events[0].data, "héllo");
+        assert_eq!(events[0].event, "message");
+    }
+
+    #[test]
+    fn test_run_events() {
+        let event = |event: &str, data: &str| SseEvent {
+            event: event.to_string(),
+            data: data.to_string(),
+        };
+
+        let delta = event(
+            "thread.message.delta",
+            r#"{"delta":{"content":[{"index":0,"type":"text","text":{"value":"Hel"}}]}}"#,
+        );
+        assert!(matches!(run_event(&delta), Ok(RunEvent::TextDelta(text)) if text == "Hel"));
+
+        let action = event(
+            "thread.run.requires_action",
+            r#"{"id":"run_1","required_action":{"type":"submit_tool_outputs","submit_tool_outputs":
+            {"tool_calls":[{"id":"call_1","type":"function","function":{"name":"date_time","arguments":"{}"}}]}}}"#,
+        );
+        match run_event(&action) {
+            Ok(RunEvent::RequiresAction(calls)) => assert_eq!(calls[0].id, "call_1"),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        let failed = event(
+            "thread.run.failed",
+            r#"{"id":"run_1","last_error":{"code":"server_error","message":"oops"}}"#,
+        );
+        assert!(matches!(run_event(&failed), Ok(RunEvent::Ended(err)) if err.contains("oops")));
+        assert!(matches!(
+            run_event(&event("done", "[DONE]")),
+            Ok(RunEvent::Done)
+        ));
+        assert!(run_event(&event("thread.run.completed", "not json")).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+//! Fixtures shared by the unit tests.
+
+use async_openai::types::AssistantObject;
+use serde_json::{json, Value};
+
+use crate::database::users::{User, UserStore};
+
+/// An assistant as the OpenAI API returns it.
+pub fn assistant_json(id: &str, name: &str) -> Value {
+    json!({
+        "id": id,
+        "object": "assistant",
+        "created_at": 1700000000,
+        "name": name,
+        "description": null,
+        "model": "gpt-4-1106-preview",
+        "instructions": null,
+        "tools": [],
+        "file_ids": [],
+        "metadata": {},
+    })
+}
+
+pub fn assistant(id: &str, name: &str) -> AssistantObject {
+    serde_json::from_value(assistant_json(id, name)).expect("Failed to build assistant")
+}
+
+/// A temporary user store with Ada registered as user 1, with a nickname, a preferred name
+/// and a timezone.
+pub fn user_store() -> UserStore {
+    let db = sled::Config::new()
+        .temporary(true)
+        .open()
+        .expect("Failed to open temporary database");
+    let users = UserStore::from_db(db);
+    let mut ada = User::new(
+        "1".to_string(),
+        "ada".to_string(),
+        Some("Ada L".to_string()),
+        Some("Countess".to_string()),
+    );
+    ada.timezone = Some("Europe/London".to_string());
+    users.register_user(&ada).expect("Failed to register user");
+    users
+}
@@ -1,27 +1,118 @@
 use async_openai::{
-    config::OpenAIConfig,
+    config::{Config, OpenAIConfig},
+    error::OpenAIError,
     types::{
         CreateMessageRequestArgs, CreateRunRequestArgs, CreateThreadRequestArgs, MessageContent,
-        RunStatus, SubmitToolOutputsRunRequest, ToolsOutputs,
+        MessageRole, RunStatus, RunToolCallObject, SubmitToolOutputsRunRequest, ToolsOutputs,
     },
     Client,
 };
-use log::debug;
+use futures::future::join_all;
+use log::{debug, error};
+use reqwest::{Response, StatusCode};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use serenity::client::Context;
+use serde_json::{json, Value};
+use serenity::{client::Context, model::id::ChannelId};
+use std::{
+    fmt,
+    sync::Mutex as StdMutex,
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc::UnboundedSender, Mutex};
 
-use crate::tools::{
-    assistant_list::AssistantListTool, available_tools, datetime::DateTimeTool, image::ImageTool,
-    transcribe::TranscribeTool, tts::TtsTool, AlvariumTool, Tools,
+use crate::{
+    config::env_or,
+    streaming::{run_event, RunEvent, SseParser},
+    tools::{ToolContext, ToolError, ToolRegistry},
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Upper bound for a single tool call, so one slow tool can't keep the whole run waiting.
+const TOOL_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// How long runs poll after the API refused to stream one, before streaming is tried again.
+const STREAMING_RETRY: Duration = Duration::from_secs(10 * 60);
+
+/// Set when the API refused to stream a run, so runs poll right away for a while.
+static STREAMING_UNAVAILABLE_UNTIL: StdMutex<Option<Instant>> = StdMutex::new(None);
+
+/// Streaming can be turned off with `STREAM_REPLIES=false`.
+fn streaming_enabled() -> bool {
+    let unavailable = STREAMING_UNAVAILABLE_UNTIL
+        .lock()
+        .expect("Failed to lock streaming state")
+        .is_some_and(|until| Instant::now() < until);
+    !unavailable && env_or("STREAM_REPLIES", true)
+}
+
+fn streaming_unavailable() {
+    *STREAMING_UNAVAILABLE_UNTIL
+        .lock()
+        .expect("Failed to lock streaming state") = Some(Instant::now() + STREAMING_RETRY);
+}
+
+/// Whether a bad request was about the `stream` parameter, rather than about the run.
+fn rejects_streaming(body: &str) -> bool {
+    let body: Value = serde_json::from_str(body).unwrap_or_default();
+    let error = &body["error"];
+    error["param"] == "stream"
+        || error["message"] == "Unrecognized request argument supplied: stream"
+}
+
+#[derive(Debug)]
+enum StreamError {
+    /// The API doesn't stream runs, nothing was started
+    Unavailable(String),
+    /// The stream broke off, the run may still be going
+    Interrupted {
+        run_id: Option<String>,
+        reason: String,
+    },
+    /// The run itself went wrong
+    Run(String),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Unavailable(err) => write!(f, "streaming unavailable: {}", err),
+            StreamError::Interrupted { reason, .. } => write!(f, "stream interrupted: {}", reason),
+            StreamError::Run(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TranscribeToolArguments {
+    /// The URL of the audio file to transcribe
     pub url: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DateTimeToolArguments {
+    /// The id or name of a registered user to get the local time of
+    pub user_id: Option<String>,
+    /// An IANA timezone like Europe/Brussels, takes precedence over the user's timezone
+    pub timezone: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WebScrapeToolArguments {
+    /// The url of the page to read
+    pub url: String,
+    /// The maximum number of characters to return, defaults to the configured limit
+    pub max_characters: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MentionToolArguments {
+    /// The id or name of the user to mention
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UserInfoToolArguments {
+    /// The id or name of the user
     pub id: String,
 }
 
@@ -30,23 +121,66 @@ pub struct MentionToolResponse {
     pub mention_format: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+pub enum ImageModelArgument {
+    #[serde(rename = "dall-e-3")]
+    DallE3,
+    #[serde(rename = "dall-e-2")]
+    DallE2,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageQualityArgument {
+    Standard,
+    Hd,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageStyleArgument {
+    Natural,
+    Vivid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ImageToolArguments {
+    /// The prompt for the image generation model
     pub prompt: String,
-    pub model: Option<String>,
-    pub quality: Option<String>,
-    pub style: Option<String>,
+    /// The model to use for image generation
+    pub model: Option<ImageModelArgument>,
+    /// The quality of the image, only applies to dall-e-3
+    pub quality: Option<ImageQualityArgument>,
+    /// The style to use for image generation, only applies to dall-e-3
+    pub style: Option<ImageStyleArgument>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum VoiceArgument {
+    Alloy,
+    Echo,
+    Fable,
+    Nova,
+    Onyx,
+    Shimmer,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TtsToolArguments {
+    /// The text to synthesize
     pub content: String,
-    pub voice: Option<String>,
+    /// The voice to use
+    pub voice: Option<VoiceArgument>,
 }
 
 pub struct OpenAIThread {
     thread_id: String,
     client: Client<OpenAIConfig>,
+    /// Messages waiting for the active run to finish
+    pending: StdMutex<Vec<String>>,
+    /// Held while a run is active, the API rejects new messages on the thread until it ends
+    run_lock: Mutex<()>,
 }
 
 impl OpenAIThread {
@@ -62,18 +196,19 @@ impl OpenAIThread {
             .await
             .expect("Failed to create thread");
 
-        OpenAIThread {
-            thread_id: thread.id,
-            client,
-        }
+        Self::with_client(&thread.id, client)
     }
 
     pub fn from_existing(thread_id: &str) -> Self {
-        let client: Client<OpenAIConfig> = Client::new();
+        Self::with_client(thread_id, Client::new())
+    }
 
+    fn with_client(thread_id: &str, client: Client<OpenAIConfig>) -> Self {
         OpenAIThread {
             thread_id: thread_id.to_owned(),
             client,
+            pending: StdMutex::new(vec![]),
+            run_lock: Mutex::new(()),
         }
     }
 
@@ -81,7 +216,68 @@ impl OpenAIThread {
         &self.thread_id
     }
 
-    pub async fn add_message(&self, message: String) {
+    /// Queues a message for the thread. It is added right away when no run is active,
+    /// otherwise once the run is over. A message that couldn't be added stays queued and
+    /// is tried again with the next one.
+    pub async fn add_message(&self, message: String) -> Result<(), String> {
+        debug!("Queueing message: {}", message);
+        self.pending
+            .lock()
+            .expect("Failed to lock pending messages")
+            .push(message);
+        self.flush_if_idle().await
+    }
+
+    /// Adds the queued messages when no run is active. Whoever holds the run lock flushes
+    /// before letting go of the thread, so nothing stays queued behind a finished run.
+    async fn flush_if_idle(&self) -> Result<(), String> {
+        loop {
+            let guard = match self.run_lock.try_lock() {
+                Ok(guard) => guard,
+                Err(_) => return Ok(()),
+            };
+            self.flush().await?;
+            drop(guard);
+
+            if self
+                .pending
+                .lock()
+                .expect("Failed to lock pending messages")
+                .is_empty()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Adds the queued messages in order, the caller must hold the run lock. When adding
+    /// one fails, it and the ones after it go back to the front of the queue.
+    async fn flush(&self) -> Result<(), String> {
+        let mut messages = std::mem::take(
+            &mut *self
+                .pending
+                .lock()
+                .expect("Failed to lock pending messages"),
+        )
+        .into_iter();
+        while let Some(message) = messages.next() {
+            if let Err(err) = self.create_message(&message).await {
+                let mut pending = self
+                    .pending
+                    .lock()
+                    .expect("Failed to lock pending messages");
+                let mut requeued = std::iter::once(message)
+                    .chain(messages)
+                    .collect::<Vec<String>>();
+                requeued.append(&mut pending);
+                *pending = requeued;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    async fn create_message(&self, message: &str) -> Result<(), String> {
         debug!("Adding message: {}", message);
         let message = CreateMessageRequestArgs::default()
             .role("user")
@@ -94,10 +290,77 @@ impl OpenAIThread {
             .messages(&self.thread_id)
             .create(message)
             .await
-            .expect("Failed to create message");
+            .map_err(|err| format!("Failed to add message: {}", err))?;
+        Ok(())
+    }
+
+    /// Runs the assistant on the thread. Runs on the same thread wait for each other, the
+    /// messages queued in the meantime are added in between. With `deltas` the answer is
+    /// streamed into it while it is written, when the API supports streaming.
+    pub async fn run(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        assistant: &str,
+        deltas: Option<UnboundedSender<String>>,
+    ) -> Result<Vec<MessageContent>, String> {
+        let result = {
+            let _guard = self.run_lock.lock().await;
+            match self.flush().await {
+                Err(err) => Err(err),
+                Ok(()) => match deltas {
+                    Some(deltas) if streaming_enabled() => {
+                        self.run_streamed_or_poll(ctx, channel_id, assistant, &deltas)
+                            .await
+                    }
+                    _ => self.run_exclusive(ctx, channel_id, assistant).await,
+                },
+            }
+        };
+        if let Err(err) = self.flush_if_idle().await {
+            error!("Failed to add queued messages: {}", err);
+        }
+        result
+    }
+
+    async fn run_streamed_or_poll(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        assistant: &str,
+        deltas: &UnboundedSender<String>,
+    ) -> Result<Vec<MessageContent>, String> {
+        match self.run_streamed(ctx, channel_id, assistant, deltas).await {
+            Ok(content) => Ok(content),
+            Err(StreamError::Run(err)) => Err(err),
+            Err(StreamError::Unavailable(err)) => {
+                error!("Streaming is unavailable, polling instead: {}", err);
+                streaming_unavailable();
+                self.run_exclusive(ctx, channel_id, assistant).await
+            }
+            Err(StreamError::Interrupted {
+                run_id: Some(run_id),
+                reason,
+            }) => {
+                error!(
+                    "Stream of run {} broke off, polling instead: {}",
+                    run_id, reason
+                );
+                self.poll_run(ctx, channel_id, assistant, &run_id).await
+            }
+            Err(StreamError::Interrupted {
+                run_id: None,
+                reason,
+            }) => Err(format!("Stream broke off: {}", reason)),
+        }
     }
 
-    pub async fn run(&self, ctx: &Context, assistant: &str) -> Result<Vec<MessageContent>, String> {
+    async fn run_exclusive(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        assistant: &str,
+    ) -> Result<Vec<MessageContent>, String> {
         debug!("Running thread {}", self.thread_id);
         let run_request = CreateRunRequestArgs::default()
             .assistant_id(assistant)
@@ -110,70 +373,47 @@ impl OpenAIThread {
             .runs(&self.thread_id)
             .create(run_request)
             .await
-            .expect("Failed to create run");
+            .map_err(|err| format!("Failed to create run: {}", err))?;
 
+        self.poll_run(ctx, channel_id, assistant, &run.id).await
+    }
+
+    async fn poll_run(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        assistant: &str,
+        run_id: &str,
+    ) -> Result<Vec<MessageContent>, String> {
         loop {
             let run = self
                 .client
                 .threads()
                 .runs(&self.thread_id)
-                .retrieve(&run.id)
+                .retrieve(run_id)
                 .await
-                .expect("Failed to retrieve run");
+                .map_err(|err| format!("Failed to retrieve run: {}", err))?;
 
             match run.status {
                 RunStatus::Cancelled => return Err("Run was cancelled".to_string()),
                 RunStatus::Cancelling => debug!("Run is cancelling"),
                 RunStatus::Failed => return Err("Run failed".to_string()),
-                RunStatus::Completed => return Ok(self.get_latest_message().await),
+                RunStatus::Completed => return self.get_latest_message().await,
                 RunStatus::Expired => return Err("Run expired".to_string()),
                 RunStatus::InProgress => debug!("Run is in progress"),
                 RunStatus::Queued => debug!("Run is queued"),
                 RunStatus::RequiresAction => {
-                    let required_action =
-                        run.required_action.expect("Failed to get required action");
-
-                    let mut outputs: Vec<ToolsOutputs> = vec![];
-                    // TODO should convert to asynchrounously run tools
-                    for tool_request in required_action.submit_tool_outputs.tool_calls {
-                        let args = tool_request.function.arguments.clone();
-                        debug!("Tool: {:?}", tool_request.function.name);
-
-                        let tools = available_tools();
-                        let tool = tools
-                            .iter()
-                            .find(|tool| tool.name() == tool_request.function.name.as_str())
-                            .expect("Failed to find tool");
-
-                        match tool {
-                            Tools::AssistantList => {
-                                let output = AssistantListTool::run((), ctx, &tool_request).await;
-                                outputs.push(output);
-                            }
-                            Tools::DateTime => {
-                                let output = DateTimeTool::run((), ctx, &tool_request).await;
-                                outputs.push(output);
-                            }
-                            Tools::Tts => {
-                                let args = serde_json::from_str::<TtsToolArguments>(&args)
-                                    .expect("Failed to deserialize arguments");
-                                let output = TtsTool::run(args, ctx, &tool_request).await;
-                                outputs.push(output);
-                            }
-                            Tools::Transcribe => {
-                                let args = serde_json::from_str::<TranscribeToolArguments>(&args)
-                                    .expect("Failed to deserialize arguments");
-                                let output = TranscribeTool::run(args, ctx, &tool_request).await;
-                                outputs.push(output);
-                            }
-                            Tools::Image => {
-                                let args = serde_json::from_str::<ImageToolArguments>(&args)
-                                    .expect("Failed to deserialize arguments");
-                                let output = ImageTool::run(args, ctx, &tool_request).await;
-                                outputs.push(output);
-                            }
-                        }
-                    }
+                    let required_action = run
+                        .required_action
+                        .ok_or_else(|| "Run requires an action but has none".to_string())?;
+                    let outputs = self
+                        .tool_outputs(
+                            ctx,
+                            channel_id,
+                            assistant,
+                            &required_action.submit_tool_outputs.tool_calls,
+                        )
+                        .await;
 
                     self.reply_tool_output(
                         &run.id,
@@ -181,23 +421,199 @@ impl OpenAIThread {
                             tool_outputs: outputs,
                         },
                     )
-                    .await;
+                    .await?;
                 }
             }
             tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
         }
     }
 
-    async fn reply_tool_output(&self, run_id: &str, output: SubmitToolOutputsRunRequest) {
+    async fn tool_outputs(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        assistant: &str,
+        tool_calls: &[RunToolCallObject],
+    ) -> Vec<ToolsOutputs> {
+        let tool_context = ToolContext {
+            discord: ctx,
+            channel_id,
+            assistant_id: assistant,
+        };
+        join_all(
+            tool_calls
+                .iter()
+                .map(|tool_request| self.run_tool_with_timeout(&tool_context, tool_request)),
+        )
+        .await
+    }
+
+    /// Starts a streamed request on the runs API. The API answers requests it can't stream
+    /// with a bad request about the `stream` parameter, before anything was started.
+    async fn stream_request(&self, path: &str, body: Value) -> Result<Response, StreamError> {
+        let config = self.client.config();
+        let response = reqwest::Client::new()
+            .post(config.url(path))
+            .headers(config.headers())
+            .json(&body)
+            .send()
+            .await
+            .map_err(|err| StreamError::Interrupted {
+                run_id: None,
+                reason: err.to_string(),
+            })?;
+
+        let status = response.status();
+        if status == StatusCode::BAD_REQUEST {
+            let body = response.text().await.unwrap_or_default();
+            let err = format!("{}: {}", status, body);
+            return Err(if rejects_streaming(&body) {
+                StreamError::Unavailable(err)
+            } else {
+                StreamError::Run(err)
+            });
+        }
+        response
+            .error_for_status()
+            .map_err(|err| StreamError::Run(err.to_string()))
+    }
+
+    async fn run_streamed(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        assistant: &str,
+        deltas: &UnboundedSender<String>,
+    ) -> Result<Vec<MessageContent>, StreamError> {
+        debug!("Streaming run on thread {}", self.thread_id);
+        let mut response = self
+            .stream_request(
+                &format!("/threads/{}/runs", self.thread_id),
+                json!({ "assistant_id": assistant, "stream": true }),
+            )
+            .await?;
+
+        let mut run_id = None;
+        let mut parser = SseParser::default();
+        loop {
+            let chunk = match response.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => {
+                    return Err(StreamError::Interrupted {
+                        run_id,
+                        reason: "stream ended early".to_string(),
+                    })
+                }
+                Err(err) => {
+                    return Err(StreamError::Interrupted {
+                        run_id,
+                        reason: err.to_string(),
+                    })
+                }
+            };
+
+            for event in parser.feed(&chunk) {
+                let event = run_event(&event).map_err(|reason| StreamError::Interrupted {
+                    run_id: run_id.clone(),
+                    reason,
+                })?;
+                match event {
+                    RunEvent::Created { run_id: id } => run_id = Some(id),
+                    RunEvent::TextDelta(text) => {
+                        // The receiving end going away only means nobody watches the stream
+                        let _ = deltas.send(text);
+                    }
+                    RunEvent::RequiresAction(tool_calls) => {
+                        let id = run_id.clone().ok_or_else(|| {
+                            StreamError::Run("Tool calls for an unknown run".to_string())
+                        })?;
+                        let outputs = self
+                            .tool_outputs(ctx, channel_id, assistant, &tool_calls)
+                            .await;
+                        response = self
+                            .stream_request(
+                                &format!(
+                                    "/threads/{}/runs/{}/submit_tool_outputs",
+                                    self.thread_id, id
+                                ),
+                                json!({ "tool_outputs": outputs, "stream": true }),
+                            )
+                            .await
+                            // Polling would run the tools again, and tools have side effects
+                            .map_err(|err| {
+                                StreamError::Run(format!("Failed to submit tool outputs: {}", err))
+                            })?;
+                        parser = SseParser::default();
+                        break;
+                    }
+                    RunEvent::Completed => {
+                        return self.get_latest_message().await.map_err(StreamError::Run)
+                    }
+                    RunEvent::Ended(err) => return Err(StreamError::Run(err)),
+                    RunEvent::Done => {
+                        return Err(StreamError::Interrupted {
+                            run_id,
+                            reason: "stream ended before the run".to_string(),
+                        })
+                    }
+                    RunEvent::Other => {}
+                }
+            }
+        }
+    }
+
+    async fn run_tool_with_timeout(
+        &self,
+        ctx: &ToolContext<'_>,
+        tool_request: &RunToolCallObject,
+    ) -> ToolsOutputs {
+        let result =
+            match tokio::time::timeout(TOOL_TIMEOUT, self.run_tool(ctx, tool_request)).await {
+                Ok(result) => result,
+                Err(_) => Err(ToolError::Timeout(TOOL_TIMEOUT)),
+            };
+
+        result.unwrap_or_else(|err| {
+            error!("Tool {} failed: {}", tool_request.function.name, err);
+            err.into_output(tool_request)
+        })
+    }
+
+    async fn run_tool(
+        &self,
+        ctx: &ToolContext<'_>,
+        tool_request: &RunToolCallObject,
+    ) -> Result<ToolsOutputs, ToolError> {
+        debug!("Tool: {:?}", tool_request.function.name);
+        let tools = {
+            let data = ctx.data.read().await;
+            data.get::<ToolRegistry>()
+                .expect("Expected ToolRegistry in TypeMap")
+                .clone()
+        };
+
+        let tool = tools
+            .get(&tool_request.function.name)
+            .ok_or_else(|| ToolError::UnknownTool(tool_request.function.name.clone()))?;
+
+        tool.call(ctx, tool_request).await
+    }
+
+    async fn reply_tool_output(
+        &self,
+        run_id: &str,
+        output: SubmitToolOutputsRunRequest,
+    ) -> Result<(), String> {
         self.client
             .threads()
             .runs(&self.thread_id)
             .submit_tool_outputs(run_id, output)
             .await
-            .expect("Failed to submit tool outputs");
+            .map_err(|err| format!("Failed to submit tool outputs: {}", err))?;
+        Ok(())
     }
 
-    async fn get_latest_message(&self) -> Vec<MessageContent> {
+    async fn get_latest_message(&self) -> Result<Vec<MessageContent>, String> {
         let query = [("limit", "1")];
         let response = self
             .client
@@ -205,14 +621,12 @@ impl OpenAIThread {
             .messages(&self.thread_id)
             .list(&query)
             .await
-            .expect("Failed to list messages");
+            .map_err(|err| format!("Failed to list messages: {}", err))?;
 
-        let message_id = response
-            .data
-            .get(0)
-            .expect("Failed to get message id")
-            .id
-            .clone();
+        let message_id = match response.data.get(0) {
+            Some(message) => message.id.clone(),
+            None => return Err("The thread has no messages".to_string()),
+        };
 
         let message = self
             .client
@@ -220,13 +634,189 @@ impl OpenAIThread {
             .messages(&self.thread_id)
             .retrieve(&message_id)
             .await
-            .expect("Failed to retrieve message");
+            .map_err(|err| format!("Failed to retrieve message: {}", err))?;
+
+        Ok(message.content)
+    }
+
+    /// The text of the last `limit` messages, oldest first. User messages already start
+    /// with the id of their author, assistant messages get an `assistant:` prefix.
+    pub async fn messages(&self, limit: u32) -> Result<Vec<String>, OpenAIError> {
+        let limit = limit.to_string();
+        let query = [("limit", limit.as_str())];
+        let response = self
+            .client
+            .threads()
+            .messages(&self.thread_id)
+            .list(&query)
+            .await?;
+
+        let messages = response
+            .data
+            .into_iter()
+            .rev()
+            .flat_map(|message| {
+                let from_assistant = matches!(message.role, MessageRole::Assistant);
+                message
+                    .content
+                    .into_iter()
+                    .filter_map(move |content| match content {
+                        MessageContent::Text(text) if from_assistant => {
+                            Some(format!("assistant: {}", text.text.value))
+                        }
+                        MessageContent::Text(text) => Some(text.text.value),
+                        MessageContent::ImageFile(_) => None,
+                    })
+            })
+            .collect();
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn message_object(content: &str) -> serde_json::Value {
+        json!({
+            "id": "msg_1",
+            "object": "thread.message",
+            "created_at": 1700000000,
+            "thread_id": "thread_1",
+            "role": "user",
+            "content": [{"type": "text", "text": {"value": content, "annotations": []}}],
+            "assistant_id": null,
+            "run_id": null,
+            "file_ids": [],
+            "metadata": {},
+        })
+    }
+
+    #[tokio::test]
+    async fn test_messages_wait_for_active_run() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/threads/thread_1/messages"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(message_object("hi")))
+            .mount(&server)
+            .await;
+        let client = Client::with_config(
+            OpenAIConfig::new()
+                .with_api_base(server.uri())
+                .with_api_key("test"),
+        );
+        let thread = OpenAIThread::with_client("thread_1", client);
+
+        let run = thread.run_lock.lock().await;
+        thread.add_message("first".to_string()).await.unwrap();
+        thread.add_message("second".to_string()).await.unwrap();
+        assert!(server.received_requests().await.unwrap().is_empty());
+
+        drop(run);
+        thread.add_message("third".to_string()).await.unwrap();
+        let contents = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                body["content"].as_str().unwrap().to_string()
+            })
+            .collect::<Vec<String>>();
+        assert_eq!(contents, vec!["first", "second", "third"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_messages_stay_queued() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/threads/thread_1/messages"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": {
+                    "message": "Thread thread_1 already has an active run run_1.",
+                    "type": "invalid_request_error",
+                    "param": null,
+                    "code": null,
+                }
+            })))
+            .mount(&server)
+            .await;
+        let client = Client::with_config(
+            OpenAIConfig::new()
+                .with_api_base(server.uri())
+                .with_api_key("test"),
+        );
+        let thread = OpenAIThread::with_client("thread_1", client);
+
+        assert!(thread.add_message("first".to_string()).await.is_err());
+        assert!(thread.add_message("second".to_string()).await.is_err());
+        assert_eq!(*thread.pending.lock().unwrap(), vec!["first", "second"]);
+    }
+
+    #[tokio::test]
+    async fn test_only_stream_errors_make_streaming_unavailable() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/threads/thread_1/runs"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": {
+                    "message": "Thread thread_1 already has an active run run_1.",
+                    "type": "invalid_request_error",
+                    "param": null,
+                }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/threads/thread_2/runs"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": {
+                    "message": "Unrecognized request argument supplied: stream",
+                    "type": "invalid_request_error",
+                    "param": null,
+                }
+            })))
+            .mount(&server)
+            .await;
+        let client = Client::with_config(
+            OpenAIConfig::new()
+                .with_api_base(server.uri())
+                .with_api_key("test"),
+        );
+        let thread = OpenAIThread::with_client("thread_1", client);
+
+        let active = thread
+            .stream_request("/threads/thread_1/runs", json!({ "stream": true }))
+            .await;
+        assert!(matches!(active, Err(StreamError::Run(err)) if err.contains("active run")));
 
-        message.content
+        let unsupported = thread
+            .stream_request("/threads/thread_2/runs", json!({ "stream": true }))
+            .await;
+        assert!(matches!(unsupported, Err(StreamError::Unavailable(_))));
     }
 
-    #[allow(dead_code)]
-    pub fn get_messages(&self) -> Vec<String> {
-        todo!()
+    #[test]
+    fn test_rejects_streaming() {
+        assert!(rejects_streaming(
+            r#"{"error":{"message":"Invalid value","param":"stream"}}"#
+        ));
+        assert!(rejects_streaming(
+            r#"{"error":{"message":"Unrecognized request argument supplied: stream","param":null}}"#
+        ));
+        assert!(!rejects_streaming(
+            r#"{"error":{"message":"Invalid model gpt-5","param":"model"}}"#
+        ));
+        assert!(!rejects_streaming(
+            r#"{"error":{"message":"Run run_1 is not streaming","param":null}}"#
+        ));
+        assert!(!rejects_streaming("not json"));
     }
 }
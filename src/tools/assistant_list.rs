@@ -1,60 +1,39 @@
 use std::collections::HashMap;
 
-use async_openai::types::{
-    AssistantTools, AssistantToolsFunction, ChatCompletionFunctions, RunToolCallObject,
-    SubmitToolOutputsRunRequest, ToolsOutputs,
-};
+use async_openai::types::{RunToolCallObject, ToolsOutputs};
 use serde::Serialize;
-use serde_json::json;
-use serenity::client::Context;
+use serenity::async_trait;
 
-use crate::openai::OpenAI;
+use crate::openai::{AssistantCache, OpenAI};
 
-use super::AlvariumTool;
+use super::{AlvariumTool, NoArguments, ToolContext, ToolError};
 
 pub struct AssistantListTool;
+#[async_trait]
 impl AlvariumTool for AssistantListTool {
-    type Arguments = ();
-    fn definition() -> AssistantTools {
-        AssistantTools::Function(AssistantToolsFunction {
-            r#type: "function".to_string(),
-            function: ChatCompletionFunctions {
-                name: "assistant_list".to_string(),
-                description: Some("List all assistants".to_string()),
-                parameters: json!({
-                    "type": "object",
-                    "properties": {},
-                    "required": []
-                }),
-            },
-        })
-    }
-
+    type Arguments = NoArguments;
     fn name() -> String {
         "assistant_list".to_owned()
     }
 
     fn description() -> String {
-        match Self::definition() {
-            AssistantTools::Function(AssistantToolsFunction { function, .. }) => {
-                function.description.unwrap_or_default()
-            }
-            _ => "".to_owned(),
-        }
+        "List all assistants".to_owned()
     }
 
     async fn run(
         _args: Self::Arguments,
-        context: &Context,
+        context: &ToolContext<'_>,
         tool: &RunToolCallObject,
-    ) -> ToolsOutputs {
+    ) -> Result<ToolsOutputs, ToolError> {
         let data_read = context.data.read().await;
         let openai = data_read
             .get::<OpenAI>()
             .expect("Expected OpenAI in ShareMap");
-        let assistants = openai
-            .assistants()
-            .await
+        let assistants = data_read
+            .get::<AssistantCache>()
+            .expect("Expected AssistantCache in TypeMap")
+            .assistants(openai)
+            .await?
             .iter()
             .map(|assistant| AssistantVm {
                 id: assistant.id.clone(),
@@ -64,12 +43,12 @@ impl AlvariumTool for AssistantListTool {
             })
             .collect::<Vec<AssistantVm>>();
 
-        ToolsOutputs {
+        Ok(ToolsOutputs {
             tool_call_id: Some(tool.id.clone()),
             output: Some(
-                serde_json::to_string_pretty(&assistants).expect("Failed to serialize assistants"),
+                serde_json::to_string_pretty(&assistants).map_err(ToolError::Serialization)?,
             ),
-        }
+        })
     }
 }
 
@@ -1,53 +1,107 @@
-use async_openai::types::{
-    AssistantTools, AssistantToolsFunction, ChatCompletionFunctions, RunToolCallObject,
-    ToolsOutputs,
-};
-use serde_json::json;
-use serenity::client::Context;
+use async_openai::types::{RunToolCallObject, ToolsOutputs};
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde_json::{json, Value};
+use serenity::async_trait;
 
-use super::AlvariumTool;
+use crate::{database::users::User, thread::DateTimeToolArguments};
+
+use super::{resolve_user, AlvariumTool, ToolContext, ToolError};
+
+fn describe<T: TimeZone>(now: DateTime<T>, timezone: &str) -> Value {
+    json!({
+        "datetime": now.to_rfc3339(),
+        "day": now.format("%A").to_string(),
+        "utc_offset": now.format("%:z").to_string(),
+        "timezone": timezone,
+    })
+}
+
+fn parse_timezone(timezone: &str) -> Result<Tz, ToolError> {
+    timezone
+        .parse::<Tz>()
+        .map_err(|_| ToolError::InvalidInput(format!("Unknown timezone: {}", timezone)))
+}
+
+/// The timezone a user registered, the server's timezone would silently be a wrong answer.
+fn user_timezone(user: &User) -> Result<Tz, ToolError> {
+    user.tz().ok_or_else(|| {
+        ToolError::InvalidInput(format!(
+            "{} has no timezone registered, they can set one with /register timezone:",
+            user.get_name()
+        ))
+    })
+}
 
 pub struct DateTimeTool;
+#[async_trait]
 impl AlvariumTool for DateTimeTool {
-    type Arguments = ();
-    fn definition() -> AssistantTools {
-        AssistantTools::Function(AssistantToolsFunction {
-            r#type: "function".to_string(),
-            function: ChatCompletionFunctions {
-                name: "datetime".to_string(),
-                description: Some("Get the current date and time".to_string()),
-                parameters: json!({
-                    "type": "object",
-                    "properties": {},
-                    "required": []
-                }),
-            },
-        })
-    }
-
+    type Arguments = DateTimeToolArguments;
     fn name() -> String {
         "datetime".to_owned()
     }
 
     fn description() -> String {
-        match Self::definition() {
-            AssistantTools::Function(AssistantToolsFunction { function, .. }) => {
-                function.description.unwrap_or_default()
-            }
-            _ => "".to_owned(),
-        }
+        "Get the current date and time, optionally in the timezone of a user".to_owned()
     }
 
     async fn run(
-        _args: Self::Arguments,
-        _context: &Context,
+        args: Self::Arguments,
+        context: &ToolContext<'_>,
         tool: &RunToolCallObject,
-    ) -> ToolsOutputs {
-        let now = chrono::Local::now(); // support time zones in the future
-        let day = now.format("%A").to_string();
-        ToolsOutputs {
+    ) -> Result<ToolsOutputs, ToolError> {
+        let timezone = match (args.timezone, args.user_id) {
+            (Some(timezone), _) => Some(parse_timezone(&timezone)?),
+            (None, Some(user_id)) => Some(user_timezone(&resolve_user(context, &user_id).await?)?),
+            (None, None) => None,
+        };
+
+        let now = Utc::now();
+        let output = match timezone {
+            Some(timezone) => describe(now.with_timezone(&timezone), timezone.name()),
+            None => describe(now.with_timezone(&chrono::Local), "server local time"),
+        };
+        Ok(ToolsOutputs {
             tool_call_id: Some(tool.id.clone()),
-            output: Some(json!({ "datetime": now.to_string(), "day": day }).to_string()),
-        }
+            output: Some(output.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 5, 9, 0, 0).unwrap();
+        let output = describe(
+            now.with_timezone(&chrono_tz::Europe::Brussels),
+            "Europe/Brussels",
+        );
+        assert_eq!(output["datetime"], "2024-01-05T10:00:00+01:00");
+        assert_eq!(output["day"], "Friday");
+        assert_eq!(output["utc_offset"], "+01:00");
+    }
+
+    #[test]
+    fn test_unknown_timezone_is_invalid_input() {
+        assert!(parse_timezone("Europe/Brussels").is_ok());
+        assert!(matches!(
+            parse_timezone("Mars/Olympus"),
+            Err(ToolError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_user_without_timezone_is_invalid_input() {
+        let mut user = User::new("1".to_string(), "ada".to_string(), None, None);
+        assert!(matches!(
+            user_timezone(&user),
+            Err(ToolError::InvalidInput(err)) if err.contains("/register timezone:")
+        ));
+
+        user.timezone = Some("Europe/Brussels".to_string());
+        assert_eq!(user_timezone(&user).unwrap(), chrono_tz::Europe::Brussels);
     }
 }
@@ -0,0 +1,83 @@
+use async_openai::types::{RunToolCallObject, ToolsOutputs};
+use serenity::async_trait;
+
+use crate::{
+    database::users::UserStore,
+    thread::{MentionToolArguments, MentionToolResponse},
+};
+
+use super::{find_user, user_store, AlvariumTool, ToolContext, ToolError};
+
+/// Discord's mention syntax for a user id.
+pub fn mention_format(user_id: &str) -> String {
+    format!("<@{}>", user_id)
+}
+
+fn mention(users: &UserStore, name_or_id: &str) -> Result<MentionToolResponse, ToolError> {
+    let user_id = match find_user(users, name_or_id) {
+        Ok(user) => user.id,
+        // Users that never registered can still be mentioned by their id
+        Err(_) if name_or_id.trim().parse::<u64>().is_ok() => name_or_id.trim().to_owned(),
+        Err(err) => return Err(err),
+    };
+    Ok(MentionToolResponse {
+        mention_format: mention_format(&user_id),
+    })
+}
+
+pub struct MentionTool;
+
+#[async_trait]
+impl AlvariumTool for MentionTool {
+    type Arguments = MentionToolArguments;
+
+    fn name() -> String {
+        "mention".to_owned()
+    }
+
+    fn description() -> String {
+        "Returns the format to use to mention a user in chat".to_owned()
+    }
+
+    async fn run(
+        args: Self::Arguments,
+        context: &ToolContext<'_>,
+        tool: &RunToolCallObject,
+    ) -> Result<ToolsOutputs, ToolError> {
+        let response = mention(&user_store(context).await.read().await, &args.id)?;
+        Ok(ToolsOutputs {
+            tool_call_id: Some(tool.id.clone()),
+            output: Some(serde_json::to_string(&response).map_err(ToolError::Serialization)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::user_store;
+
+    fn mentioned(users: &UserStore, name_or_id: &str) -> Result<String, ToolError> {
+        mention(users, name_or_id).map(|response| response.mention_format)
+    }
+
+    #[test]
+    fn test_mention_registered_user() {
+        let users = user_store();
+        assert_eq!(mentioned(&users, "1").unwrap(), "<@1>");
+        assert_eq!(mentioned(&users, "<@1>").unwrap(), "<@1>");
+        assert_eq!(mentioned(&users, "<@!1>").unwrap(), "<@1>");
+        assert_eq!(mentioned(&users, "Countess").unwrap(), "<@1>");
+        assert_eq!(mentioned(&users, "@ada").unwrap(), "<@1>");
+    }
+
+    #[test]
+    fn test_mention_unregistered_user() {
+        let users = user_store();
+        assert_eq!(mentioned(&users, "42").unwrap(), "<@42>");
+        assert!(matches!(
+            mentioned(&users, "grace"),
+            Err(ToolError::NotFound(_))
+        ));
+    }
+}
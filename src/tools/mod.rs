@@ -1,100 +1,399 @@
-use async_openai::types::{AssistantTools, RunToolCallObject, SubmitToolOutputsRunRequest, ToolsOutputs};
-use serenity::client::Context;
+use std::{collections::BTreeMap, fmt, ops::Deref, sync::Arc, time::Duration};
 
-use crate::tools::image::ImageTool;
+use async_openai::{
+    error::OpenAIError,
+    types::{
+        AssistantTools, AssistantToolsFunction, ChatCompletionFunctions, RunToolCallObject,
+        ToolsOutputs,
+    },
+};
+use schemars::{gen::SchemaSettings, JsonSchema};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use serenity::{async_trait, client::Context, model::id::ChannelId, prelude::RwLock};
+
+use crate::{
+    database::users::{User, UserStore},
+    tools::image::ImageTool,
+};
 
 use self::{
     assistant_list::AssistantListTool,
-    transcribe::TranscribeTool, tts::TtsTool, datetime::DateTimeTool,
+    datetime::DateTimeTool,
+    mention::MentionTool,
+    tasks::{TaskCompleteTool, TaskCreateTool, TaskListTool, TaskUpdateTool},
+    transcribe::TranscribeTool,
+    tts::TtsTool,
+    user_info::UserInfoTool,
+    web_scrape::WebScrapeTool,
 };
 
 pub mod assistant_list;
+pub mod datetime;
 pub mod image;
+pub mod mention;
+pub mod tasks;
 pub mod transcribe;
 pub mod tts;
-pub mod datetime;
+pub mod user_info;
 pub mod web_scrape;
-pub enum Tools {
-    AssistantList,
-    Image,
-    Transcribe,
-    Tts,
-    DateTime
-}
-
-impl Tools {
-    pub fn from_name(name: &str) -> Option<Self> {
-        match name {
-            "assistant_list" => Some(Tools::AssistantList),
-            "image" => Some(Tools::Image),
-            "transcribe" => Some(Tools::Transcribe),
-            "tts" => Some(Tools::Tts),
-            "datetime" => Some(Tools::DateTime),
-            _ => None,
+
+/// The Discord context a tool call was made from. Derefs to the serenity [`Context`].
+pub struct ToolContext<'a> {
+    pub discord: &'a Context,
+    pub channel_id: ChannelId,
+    pub assistant_id: &'a str,
+}
+
+impl Deref for ToolContext<'_> {
+    type Target = Context;
+
+    fn deref(&self) -> &Context {
+        self.discord
+    }
+}
+
+pub(crate) async fn user_store(context: &Context) -> Arc<RwLock<UserStore>> {
+    let data_read = context.data.read().await;
+    data_read
+        .get::<UserStore>()
+        .expect("Expected UserStore in TypeMap")
+        .clone()
+}
+
+/// Looks a registered user up by id, mention or name, as given by the assistant.
+pub(crate) fn find_user(users: &UserStore, name_or_id: &str) -> Result<User, ToolError> {
+    users
+        .find_user(name_or_id)
+        .ok_or_else(|| ToolError::NotFound(format!("Unknown user: {}", name_or_id)))
+}
+
+pub(crate) async fn resolve_user(context: &Context, name_or_id: &str) -> Result<User, ToolError> {
+    let users = user_store(context).await;
+    let users = users.read().await;
+    find_user(&users, name_or_id)
+}
+
+/// Object safe view on an [`AlvariumTool`], used to store tools of different argument
+/// types side by side in the [`ToolRegistry`].
+#[async_trait]
+pub trait RegisteredTool: Send + Sync {
+    fn name(&self) -> String;
+    fn description(&self) -> String;
+    fn definition(&self) -> AssistantTools;
+    async fn call(
+        &self,
+        context: &ToolContext<'_>,
+        tool: &RunToolCallObject,
+    ) -> Result<ToolsOutputs, ToolError>;
+}
+
+#[async_trait]
+impl<T> RegisteredTool for T
+where
+    T: AlvariumTool + Send + Sync,
+{
+    fn name(&self) -> String {
+        <T as AlvariumTool>::name()
+    }
+
+    fn description(&self) -> String {
+        <T as AlvariumTool>::description()
+    }
+
+    fn definition(&self) -> AssistantTools {
+        <T as AlvariumTool>::definition()
+    }
+
+    async fn call(
+        &self,
+        context: &ToolContext<'_>,
+        tool: &RunToolCallObject,
+    ) -> Result<ToolsOutputs, ToolError> {
+        let args = serde_json::from_str::<T::Arguments>(&tool.function.arguments)
+            .map_err(ToolError::InvalidArguments)?;
+        T::run(args, context, tool).await
+    }
+}
+
+pub struct ToolRegistry {
+    tools: BTreeMap<String, Box<dyn RegisteredTool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        ToolRegistry {
+            tools: BTreeMap::new(),
         }
     }
 
-    pub fn name(&self) -> String {
-        match self {
-            Tools::AssistantList => AssistantListTool::name(),
-            Tools::Image => ImageTool::name(),
-            Tools::Transcribe => TranscribeTool::name(),
-            Tools::Tts => TtsTool::name(),
-            Tools::DateTime => DateTimeTool::name(),
+    pub fn with<T>(mut self, tool: T) -> Self
+    where
+        T: AlvariumTool + Send + Sync + 'static,
+    {
+        let name = <T as AlvariumTool>::name();
+        if self.tools.insert(name.clone(), Box::new(tool)).is_some() {
+            panic!("Tool {} is registered more than once", name);
         }
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn RegisteredTool> {
+        self.tools.get(name).map(|tool| tool.as_ref())
+    }
+
+    pub fn tools(&self) -> impl Iterator<Item = &dyn RegisteredTool> + '_ {
+        self.tools.values().map(|tool| tool.as_ref())
     }
 
-    pub fn definition(&self) -> AssistantTools {
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+}
+
+pub fn available_tools() -> ToolRegistry {
+    ToolRegistry::new()
+        .with(AssistantListTool)
+        .with(ImageTool)
+        .with(TranscribeTool)
+        .with(TtsTool)
+        .with(DateTimeTool)
+        .with(TaskCreateTool)
+        .with(TaskListTool)
+        .with(TaskCompleteTool)
+        .with(TaskUpdateTool)
+        .with(WebScrapeTool)
+        .with(MentionTool)
+        .with(UserInfoTool)
+}
+
+/// Arguments for tools that don't take any parameters.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NoArguments {}
+
+/// Errors a tool can run into. They are reported back to the assistant as the tool
+/// output instead of aborting the run, so it can explain the failure or retry.
+#[derive(Debug)]
+pub enum ToolError {
+    UnknownTool(String),
+    InvalidArguments(serde_json::Error),
+    /// The arguments parsed, but a value in them can't be used
+    InvalidInput(String),
+    NotFound(String),
+    OpenAI(OpenAIError),
+    Http(reqwest::Error),
+    Storage(String),
+    Process(String),
+    Serialization(serde_json::Error),
+    Timeout(Duration),
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Tools::AssistantList => AssistantListTool::definition(),
-            Tools::Image => ImageTool::definition(),
-            Tools::Transcribe => TranscribeTool::definition(),
-            Tools::Tts => TtsTool::definition(),
-            Tools::DateTime => DateTimeTool::definition(),
+            ToolError::UnknownTool(name) => write!(f, "Unknown tool: {}", name),
+            ToolError::InvalidArguments(err) => write!(f, "Invalid arguments: {}", err),
+            ToolError::InvalidInput(message) => write!(f, "Invalid input: {}", message),
+            ToolError::NotFound(message) => write!(f, "{}", message),
+            ToolError::OpenAI(OpenAIError::ApiError(err)) => write!(f, "{}", err.message),
+            ToolError::OpenAI(err) => write!(f, "OpenAI request failed: {}", err),
+            ToolError::Http(err) => write!(f, "Request failed: {}", err),
+            ToolError::Storage(message) => write!(f, "Storage failure: {}", message),
+            ToolError::Process(message) => write!(f, "{}", message),
+            ToolError::Serialization(err) => write!(f, "Failed to serialize output: {}", err),
+            ToolError::Timeout(timeout) => {
+                write!(f, "Tool timed out after {} seconds", timeout.as_secs())
+            }
         }
     }
+}
 
-    pub fn description(&self) -> String {
-        match self {
-            Tools::AssistantList => AssistantListTool::description(),
-            Tools::Image => ImageTool::description(),
-            Tools::Transcribe => TranscribeTool::description(),
-            Tools::Tts => TtsTool::description(),
-            Tools::DateTime => DateTimeTool::description(),
+impl std::error::Error for ToolError {}
+
+impl From<OpenAIError> for ToolError {
+    fn from(err: OpenAIError) -> Self {
+        ToolError::OpenAI(err)
+    }
+}
+
+impl From<reqwest::Error> for ToolError {
+    fn from(err: reqwest::Error) -> Self {
+        ToolError::Http(err)
+    }
+}
+
+impl ToolError {
+    pub fn into_output(self, tool: &RunToolCallObject) -> ToolsOutputs {
+        ToolsOutputs {
+            tool_call_id: Some(tool.id.clone()),
+            output: Some(json!({ "error": self.to_string() }).to_string()),
         }
-    
     }
 }
 
-pub fn available_tools() -> Vec<Tools> {
-    vec![
-        Tools::AssistantList,
-        Tools::Image,
-        Tools::Transcribe,
-        Tools::Tts,
-        Tools::DateTime
-    ]
+/// JSON schema of a tool's arguments in the shape the assistants API expects, generated
+/// from the `Arguments` type so the two can't drift apart.
+pub fn parameters<T: JsonSchema>() -> serde_json::Value {
+    let settings = SchemaSettings::draft07().with(|settings| {
+        settings.option_nullable = false;
+        settings.option_add_null_type = false;
+        settings.inline_subschemas = true;
+        settings.meta_schema = None;
+    });
+    let schema = settings.into_generator().into_root_schema_for::<T>();
+
+    let mut parameters = serde_json::to_value(schema.schema).expect("Failed to serialize schema");
+    if let Some(parameters) = parameters.as_object_mut() {
+        parameters.remove("title");
+        parameters.entry("properties").or_insert_with(|| json!({}));
+    }
+    parameters
 }
 
+#[async_trait]
 pub trait AlvariumTool {
-    type Arguments: Send + Sync;
+    type Arguments: DeserializeOwned + JsonSchema + Send + Sync;
 
     fn name() -> String;
     fn description() -> String;
-    fn definition() -> AssistantTools;
+
+    fn definition() -> AssistantTools {
+        AssistantTools::Function(AssistantToolsFunction {
+            r#type: "function".to_string(),
+            function: ChatCompletionFunctions {
+                name: Self::name(),
+                description: Some(Self::description()),
+                parameters: parameters::<Self::Arguments>(),
+            },
+        })
+    }
+
     async fn run(
         args: Self::Arguments,
-        context: &Context,
+        context: &ToolContext<'_>,
         tool: &RunToolCallObject,
-    ) -> ToolsOutputs;
+    ) -> Result<ToolsOutputs, ToolError>;
 }
 
 #[cfg(test)]
 mod tests {
+    use serde_json::{Map, Value};
+
     use super::*;
 
+    fn sample_value(schema: &Value) -> Value {
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            return values.first().cloned().expect("Empty enum in schema");
+        }
+        match schema.get("type").and_then(Value::as_str) {
+            Some("string") => json!("sample"),
+            Some("integer") => json!(1),
+            Some("number") => json!(1.5),
+            Some("boolean") => json!(true),
+            Some("array") => json!([]),
+            Some("object") => json!({}),
+            other => panic!("Unsupported schema type {:?} in {}", other, schema),
+        }
+    }
+
+    /// Builds arguments from the advertised schema, like the model would, and checks
+    /// the tool's `Arguments` type accepts every one of them.
+    fn assert_schema_matches<T: AlvariumTool>() {
+        let function = match T::definition() {
+            AssistantTools::Function(AssistantToolsFunction { function, .. }) => function,
+            _ => panic!("Tool {} is not a function tool", T::name()),
+        };
+        assert_eq!(function.name, T::name());
+
+        let parameters = function.parameters;
+        assert_eq!(parameters["type"], "object", "{}", T::name());
+        let properties = parameters["properties"]
+            .as_object()
+            .cloned()
+            .unwrap_or_default();
+        let required = parameters["required"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+
+        let mut minimal = Map::new();
+        for name in &required {
+            let name = name.as_str().expect("Required entry is not a string");
+            let property = properties
+                .get(name)
+                .unwrap_or_else(|| panic!("{} requires unknown field {}", T::name(), name));
+            minimal.insert(name.to_string(), sample_value(property));
+        }
+        serde_json::from_value::<T::Arguments>(Value::Object(minimal.clone()))
+            .unwrap_or_else(|err| panic!("{} rejects its required arguments: {}", T::name(), err));
+
+        for (name, property) in &properties {
+            let values = match property.get("enum").and_then(Value::as_array) {
+                Some(values) => values.clone(),
+                None => vec![sample_value(property)],
+            };
+            for value in values {
+                let mut arguments = minimal.clone();
+                arguments.insert(name.clone(), value.clone());
+                serde_json::from_value::<T::Arguments>(Value::Object(arguments)).unwrap_or_else(
+                    |err| panic!("{} rejects {} = {}: {}", T::name(), name, value, err),
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_tools() {
-        assert_eq!(available_tools().len(), std::mem::variant_count::<Tools>());
+        let registry = available_tools();
+        assert!(!registry.is_empty());
+        for tool in registry.tools() {
+            match tool.definition() {
+                AssistantTools::Function(AssistantToolsFunction { function, .. }) => {
+                    assert_eq!(function.name, tool.name());
+                    assert!(registry.get(&function.name).is_some());
+                }
+                _ => panic!("Tool {} is not a function tool", tool.name()),
+            }
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[should_panic]
+    fn test_tools_duplicate_name() {
+        ToolRegistry::new().with(DateTimeTool).with(DateTimeTool);
+    }
+
+    #[test]
+    fn test_tool_schemas() {
+        assert_schema_matches::<AssistantListTool>();
+        assert_schema_matches::<DateTimeTool>();
+        assert_schema_matches::<ImageTool>();
+        assert_schema_matches::<TranscribeTool>();
+        assert_schema_matches::<TtsTool>();
+        assert_schema_matches::<TaskCreateTool>();
+        assert_schema_matches::<TaskListTool>();
+        assert_schema_matches::<TaskCompleteTool>();
+        assert_schema_matches::<TaskUpdateTool>();
+        assert_schema_matches::<WebScrapeTool>();
+        assert_schema_matches::<MentionTool>();
+        assert_schema_matches::<UserInfoTool>();
+    }
+
+    #[test]
+    fn test_enum_schema() {
+        let parameters = parameters::<crate::thread::ImageToolArguments>();
+        assert_eq!(parameters["required"], json!(["prompt"]));
+        assert_eq!(
+            parameters["properties"]["model"]["enum"],
+            json!(["dall-e-3", "dall-e-2"])
+        );
+        assert_eq!(
+            parameters["properties"]["model"]["description"],
+            "The model to use for image generation"
+        );
+    }
+}
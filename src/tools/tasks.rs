@@ -0,0 +1,213 @@
+use async_openai::types::{RunToolCallObject, ToolsOutputs};
+use chrono::Utc;
+use serde_json::json;
+use serenity::{async_trait, client::Context};
+
+use crate::database::{
+    tasks::{
+        parse_due_date, CompleteTaskToolArguments, CreateTaskToolArguments, ListTaskToolArguments,
+        Task, TaskFilter, TaskStatus, TaskStore, UpdateTaskToolArguments,
+    },
+    users::User,
+};
+
+use super::{resolve_user, AlvariumTool, ToolContext, ToolError};
+
+async fn task_store(context: &Context) -> TaskStore {
+    let data_read = context.data.read().await;
+    data_read
+        .get::<TaskStore>()
+        .expect("Expected TaskStore in TypeMap")
+        .clone()
+}
+
+fn user_task(store: &TaskStore, user: &User, task_id: &str) -> Result<Task, ToolError> {
+    match store.get(task_id).map_err(ToolError::Storage)? {
+        Some(task) if task.user_id == user.id => Ok(task),
+        _ => Err(ToolError::NotFound(format!(
+            "{} has no task with id {}",
+            user.get_name(),
+            task_id
+        ))),
+    }
+}
+
+/// Parses a date given by the assistant, reading dates without an offset in the
+/// user's timezone.
+fn parse_date(
+    input: Option<String>,
+    user: &User,
+) -> Result<Option<chrono::DateTime<Utc>>, ToolError> {
+    let timezone = user.tz().unwrap_or(chrono_tz::UTC);
+    input
+        .map(|input| parse_due_date(&input, &timezone))
+        .transpose()
+        .map_err(ToolError::InvalidInput)
+}
+
+fn task_output(tool: &RunToolCallObject, user: &User, task: &Task) -> ToolsOutputs {
+    ToolsOutputs {
+        tool_call_id: Some(tool.id.clone()),
+        output: Some(
+            json!({ "user_id": user.id, "user": user.get_name(), "task": task }).to_string(),
+        ),
+    }
+}
+
+pub struct TaskCreateTool;
+
+#[async_trait]
+impl AlvariumTool for TaskCreateTool {
+    type Arguments = CreateTaskToolArguments;
+
+    fn name() -> String {
+        "task_create".to_owned()
+    }
+
+    fn description() -> String {
+        "Create a task on the to-do list of a user".to_owned()
+    }
+
+    async fn run(
+        args: Self::Arguments,
+        context: &ToolContext<'_>,
+        tool: &RunToolCallObject,
+    ) -> Result<ToolsOutputs, ToolError> {
+        let user = resolve_user(context, &args.user_id).await?;
+
+        let mut task = Task::new(&user.id, &args.title);
+        task.description = args.description;
+        task.due_date = parse_date(args.due_date, &user)?;
+        task.estimated_time = args.estimated_time;
+        task.priority = args.priority.unwrap_or_default();
+        task.remind_via = args.remind_via.unwrap_or_default();
+        task.channel_id = Some(context.channel_id.get());
+        task.assistant_id = Some(context.assistant_id.to_owned());
+
+        let task = task_store(context)
+            .await
+            .create(task)
+            .map_err(ToolError::Storage)?;
+
+        Ok(task_output(tool, &user, &task))
+    }
+}
+
+pub struct TaskListTool;
+
+#[async_trait]
+impl AlvariumTool for TaskListTool {
+    type Arguments = ListTaskToolArguments;
+
+    fn name() -> String {
+        "task_list".to_owned()
+    }
+
+    fn description() -> String {
+        "List the tasks of a user, filtered by status and due date".to_owned()
+    }
+
+    async fn run(
+        args: Self::Arguments,
+        context: &ToolContext<'_>,
+        tool: &RunToolCallObject,
+    ) -> Result<ToolsOutputs, ToolError> {
+        let user = resolve_user(context, &args.user_id).await?;
+        let filter = TaskFilter {
+            statuses: args
+                .status
+                .unwrap_or_else(|| vec![TaskStatus::Open, TaskStatus::InProgress]),
+            due_after: parse_date(args.due_after, &user)?,
+            due_before: parse_date(args.due_before, &user)?,
+        };
+
+        let tasks = task_store(context)
+            .await
+            .list(&user.id, &filter)
+            .map_err(ToolError::Storage)?;
+
+        Ok(ToolsOutputs {
+            tool_call_id: Some(tool.id.clone()),
+            output: Some(
+                json!({ "user_id": user.id, "user": user.get_name(), "tasks": tasks }).to_string(),
+            ),
+        })
+    }
+}
+
+pub struct TaskCompleteTool;
+
+#[async_trait]
+impl AlvariumTool for TaskCompleteTool {
+    type Arguments = CompleteTaskToolArguments;
+
+    fn name() -> String {
+        "task_complete".to_owned()
+    }
+
+    fn description() -> String {
+        "Mark a task of a user as done".to_owned()
+    }
+
+    async fn run(
+        args: Self::Arguments,
+        context: &ToolContext<'_>,
+        tool: &RunToolCallObject,
+    ) -> Result<ToolsOutputs, ToolError> {
+        let user = resolve_user(context, &args.user_id).await?;
+        let store = task_store(context).await;
+
+        let mut task = user_task(&store, &user, &args.task_id)?;
+        task.set_status(TaskStatus::Done);
+        store.update(&task).map_err(ToolError::Storage)?;
+
+        Ok(task_output(tool, &user, &task))
+    }
+}
+
+pub struct TaskUpdateTool;
+
+#[async_trait]
+impl AlvariumTool for TaskUpdateTool {
+    type Arguments = UpdateTaskToolArguments;
+
+    fn name() -> String {
+        "task_update".to_owned()
+    }
+
+    fn description() -> String {
+        "Update the status, priority, due date or details of a task".to_owned()
+    }
+
+    async fn run(
+        args: Self::Arguments,
+        context: &ToolContext<'_>,
+        tool: &RunToolCallObject,
+    ) -> Result<ToolsOutputs, ToolError> {
+        let user = resolve_user(context, &args.user_id).await?;
+        let store = task_store(context).await;
+
+        let mut task = user_task(&store, &user, &args.task_id)?;
+        if let Some(status) = args.status {
+            task.set_status(status);
+        }
+        if let Some(priority) = args.priority {
+            task.priority = priority;
+        }
+        if let Some(due_date) = parse_date(args.due_date, &user)? {
+            task.set_due_date(due_date);
+        }
+        if let Some(remind_via) = args.remind_via {
+            task.remind_via = remind_via;
+        }
+        if let Some(title) = args.title {
+            task.title = title;
+        }
+        if let Some(description) = args.description {
+            task.description = Some(description);
+        }
+        store.update(&task).map_err(ToolError::Storage)?;
+
+        Ok(task_output(tool, &user, &task))
+    }
+}
@@ -1,65 +1,47 @@
-use async_openai::types::{
-    AssistantTools, AssistantToolsFunction, ChatCompletionFunctions, RunToolCallObject,
-    SubmitToolOutputsRunRequest, ToolsOutputs,
-};
+use async_openai::types::{RunToolCallObject, ToolsOutputs};
 use log::debug;
 use serde_json::json;
+use serenity::async_trait;
 
 use crate::{openai::OpenAI, thread::TranscribeToolArguments};
 
-use super::AlvariumTool;
+use super::{AlvariumTool, ToolContext, ToolError};
 
 pub struct TranscribeTool;
+#[async_trait]
 impl AlvariumTool for TranscribeTool {
     type Arguments = TranscribeToolArguments;
     fn name() -> String {
         "transcribe".to_string()
     }
 
-    fn definition() -> AssistantTools {
-        AssistantTools::Function(AssistantToolsFunction {
-            r#type: "function".to_string(),
-            function: ChatCompletionFunctions {
-                name: "transcribe".to_string(),
-                description: Some("Transcribe a video to text".to_string()),
-                parameters: serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "url": {
-                            "type": "string",
-                            "description": "The URL of the audio file to transcribe"
-                        }
-                    },
-                    "required": ["url"],
-                }),
-            },
-        })
-    }
-
     fn description() -> String {
-        match Self::definition() {
-            AssistantTools::Function(AssistantToolsFunction { function, .. }) => {
-                function.description.unwrap_or_default()
-            }
-            _ => "".to_owned(),
-        }
+        "Transcribe a video to text".to_owned()
     }
 
     async fn run(
         args: Self::Arguments,
-        context: &serenity::prelude::Context,
+        context: &ToolContext<'_>,
         tool: &RunToolCallObject,
-    ) -> ToolsOutputs {
-        let data_read = context.data.read().await;
-        let openai = data_read
-            .get::<OpenAI>()
-            .expect("Expected OpenAI in ShareMap");
+    ) -> Result<ToolsOutputs, ToolError> {
+        let openai = {
+            let data_read = context.data.read().await;
+            data_read
+                .get::<OpenAI>()
+                .expect("Expected OpenAI in ShareMap")
+                .clone()
+        };
 
-        let transcript = openai.stt(&args.url).expect("Failed to transcribe");
+        // yt-dlp and whisper are blocking processes, keep them off the runtime so
+        // other tool calls of the same run can make progress
+        let transcript = tokio::task::spawn_blocking(move || openai.stt(&args.url))
+            .await
+            .map_err(|err| ToolError::Process(format!("Transcription task failed: {}", err)))?
+            .map_err(ToolError::Process)?;
         debug!("Transcript: {}", transcript);
-        ToolsOutputs {
+        Ok(ToolsOutputs {
             tool_call_id: Some(tool.id.clone()),
             output: Some(json!({"transcript": transcript}).to_string()),
-        }
+        })
     }
 }
@@ -1,79 +1,47 @@
-use async_openai::types::{
-    AssistantTools, AssistantToolsFunction, ChatCompletionFunctions, RunToolCallObject,
-    SpeechModel, SubmitToolOutputsRunRequest, ToolsOutputs, Voice,
-};
+use async_openai::types::{RunToolCallObject, SpeechModel, ToolsOutputs, Voice};
 use log::debug;
 use serde_json::json;
+use serenity::async_trait;
 
-use crate::{database::blob::Minio, openai::OpenAI, thread::TtsToolArguments};
+use crate::{
+    database::blob::Minio,
+    openai::OpenAI,
+    thread::{TtsToolArguments, VoiceArgument},
+};
 
-use super::AlvariumTool;
+use super::{AlvariumTool, ToolContext, ToolError};
+
+impl From<VoiceArgument> for Voice {
+    fn from(voice: VoiceArgument) -> Self {
+        match voice {
+            VoiceArgument::Alloy => Voice::Alloy,
+            VoiceArgument::Echo => Voice::Echo,
+            VoiceArgument::Fable => Voice::Fable,
+            VoiceArgument::Nova => Voice::Nova,
+            VoiceArgument::Onyx => Voice::Onyx,
+            VoiceArgument::Shimmer => Voice::Shimmer,
+        }
+    }
+}
 
 pub struct TtsTool;
+#[async_trait]
 impl AlvariumTool for TtsTool {
     type Arguments = TtsToolArguments;
     fn name() -> String {
         "tts".to_string()
     }
 
-    fn definition() -> async_openai::types::AssistantTools {
-        AssistantTools::Function(AssistantToolsFunction {
-            r#type: "function".to_string(),
-            function: ChatCompletionFunctions {
-                name: "tts".to_string(),
-                description: Some("Generate a voice from a prompt".to_string()),
-                parameters: serde_json::json!({
-                    "type": "object",
-                    "properties": {
-                        "content": {
-                            "type": "string",
-                            "description": "The text to synthesize"
-                        },
-                        "voice": {
-                            "type": "string",
-                            "enum": [
-                                "alloy",
-                                "echo",
-                                "fable",
-                                "nova",
-                                "onyx",
-                                "shimmer"
-                            ],
-                            "description": "The voice to use"
-                        }
-                    },
-                    "required": ["content"],
-                }),
-            },
-        })
-    }
-
     fn description() -> String {
-        match Self::definition() {
-            AssistantTools::Function(AssistantToolsFunction { function, .. }) => {
-                function.description.unwrap_or_default()
-            }
-            _ => "".to_owned(),
-        }
+        "Generate a voice from a prompt".to_owned()
     }
 
     async fn run(
         args: Self::Arguments,
-        context: &serenity::prelude::Context,
+        context: &ToolContext<'_>,
         tool: &RunToolCallObject,
-    ) -> ToolsOutputs {
-        let voice = match args.voice {
-            Some(text) => match text.as_str() {
-                "alloy" => Voice::Alloy,
-                "echo" => Voice::Echo,
-                "fable" => Voice::Fable,
-                "nova" => Voice::Nova,
-                "onyx" => Voice::Onyx,
-                "shimmer" => Voice::Shimmer,
-                _ => Voice::Nova,
-            },
-            None => Voice::Nova,
-        };
+    ) -> Result<ToolsOutputs, ToolError> {
+        let voice = args.voice.map(Voice::from).unwrap_or(Voice::Nova);
 
         let quality = SpeechModel::Tts1;
 
@@ -82,32 +50,27 @@ impl AlvariumTool for TtsTool {
             args.content, voice, quality
         );
 
-        let data_read = context.data.read().await;
-        let openai = data_read
-            .get::<OpenAI>()
-            .expect("Expected OpenAI in ShareMap");
-
-        let result = openai
-            .tts(&args.content, voice, quality)
-            .await
-            .expect("Failed to generate voice");
+        let result = {
+            let data_read = context.data.read().await;
+            let openai = data_read
+                .get::<OpenAI>()
+                .expect("Expected OpenAI in ShareMap");
+            openai.tts(&args.content, voice, quality).await?
+        };
 
         let file_name = rand::random::<u64>().to_string();
         let file_location = format!("./voice/{}.mp3", file_name);
 
-        result
-            .save(&file_location)
-            .await
-            .expect("Failed to save voice");
+        result.save(&file_location).await?;
 
         let url = Minio::new()
             .upload_mp3(&file_location)
             .await
-            .expect("Failed to upload voice");
+            .map_err(ToolError::Storage)?;
 
-        ToolsOutputs {
+        Ok(ToolsOutputs {
             tool_call_id: Some(tool.id.clone()),
             output: Some(json!({"url": url}).to_string()),
-        }
+        })
     }
 }
@@ -0,0 +1,73 @@
+use async_openai::types::{RunToolCallObject, ToolsOutputs};
+use serde_json::{json, Value};
+use serenity::async_trait;
+
+use crate::{database::users::UserStore, thread::UserInfoToolArguments};
+
+use super::{find_user, mention::mention_format, user_store, AlvariumTool, ToolContext, ToolError};
+
+fn user_info(users: &UserStore, name_or_id: &str) -> Result<Value, ToolError> {
+    let user = find_user(users, name_or_id)?;
+    Ok(json!({
+        "display_name": user.get_name(),
+        "mention_format": mention_format(&user.id),
+        "user": user,
+    }))
+}
+
+pub struct UserInfoTool;
+
+#[async_trait]
+impl AlvariumTool for UserInfoTool {
+    type Arguments = UserInfoToolArguments;
+
+    fn name() -> String {
+        "user_info".to_owned()
+    }
+
+    fn description() -> String {
+        "Returns the info of a registered user, like the name to address them by".to_owned()
+    }
+
+    async fn run(
+        args: Self::Arguments,
+        context: &ToolContext<'_>,
+        tool: &RunToolCallObject,
+    ) -> Result<ToolsOutputs, ToolError> {
+        let info = user_info(&user_store(context).await.read().await, &args.id)?;
+        Ok(ToolsOutputs {
+            tool_call_id: Some(tool.id.clone()),
+            output: Some(info.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::user_store;
+
+    #[test]
+    fn test_user_info_lookups() {
+        let users = user_store();
+        for name_or_id in ["1", "<@1>", "ada", "Ada L", "countess"] {
+            let info = user_info(&users, name_or_id).unwrap();
+            assert_eq!(info["display_name"], "Countess");
+            assert_eq!(info["mention_format"], "<@1>");
+            assert_eq!(info["user"]["timezone"], "Europe/London");
+        }
+    }
+
+    #[test]
+    fn test_unknown_user() {
+        let users = user_store();
+        assert!(matches!(
+            user_info(&users, "grace"),
+            Err(ToolError::NotFound(_))
+        ));
+        assert!(matches!(
+            user_info(&users, "42"),
+            Err(ToolError::NotFound(_))
+        ));
+    }
+}
@@ -1,60 +1,290 @@
-use async_openai::types::{
-    AssistantTools, AssistantToolsFunction, ChatCompletionFunctions, RunToolCallObject,
-    ToolsOutputs,
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use async_openai::types::{RunToolCallObject, ToolsOutputs};
+use reqwest::{
+    header::{CONTENT_TYPE, LOCATION},
+    redirect::Policy,
+    Client, Response, Url,
 };
 use serde_json::json;
-use serenity::client::Context;
+use serenity::async_trait;
 
-use super::AlvariumTool;
+use crate::{config::env_or, thread::WebScrapeToolArguments};
 
-pub struct WebScrapeTool;
+use super::{AlvariumTool, ToolContext, ToolError};
 
-impl AlvariumTool for WebScrapeTool {
-    type Arguments = ();
-
-    fn definition() -> AssistantTools {
-        AssistantTools::Function(AssistantToolsFunction {
-            r#type: "function".to_string(),
-            function: ChatCompletionFunctions {
-                name: "web_scrape".to_string(),
-                description: Some(
-                    "Scrape a web page and get its content in plain text".to_string(),
-                ),
-                parameters: json!({
-                    "type": "object",
-                    "properties": {
-                        "url": {
-                            "type": "string",
-                            "description": "The URL of the web page to scrape"
-                        }
-                    },
-                    "required": ["url"]
-                }),
-            },
-        })
+const MAX_REDIRECTS: usize = 5;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+/// Pages larger than this are cut off while downloading.
+const MAX_BYTES: usize = 2 * 1024 * 1024;
+const TEXT_WIDTH: usize = 120;
+
+/// The default character budget of the output, configurable through
+/// `WEB_SCRAPE_MAX_CHARS`. Roughly four characters make up a token.
+fn max_characters() -> usize {
+    env_or("WEB_SCRAPE_MAX_CHARS", 8000)
+}
+
+/// Cuts `text` down to at most `max` characters, returning whether anything was removed.
+fn truncate_chars(text: &str, max: usize) -> (String, bool) {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => (text[..end].to_string(), true),
+        None => (text.to_string(), false),
+    }
+}
+
+/// Whether `ip` is reachable from the internet. Anything else, like loopback, private
+/// ranges or the cloud metadata endpoint, belongs to the network the bot runs in.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || first == 0
+                // Shared address space, 100.64.0.0/10
+                || (first == 100 && second & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local, fc00::/7, and link-local, fe80::/10
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Host of the Minio endpoint, which may be public but isn't the assistants' to read.
+fn storage_host() -> Option<String> {
+    let url = env::var("S3_URL").ok()?;
+    let url = if url.contains("://") {
+        url
+    } else {
+        format!("http://{}", url)
+    };
+    Url::parse(&url).ok()?.host_str().map(str::to_lowercase)
+}
+
+/// Builds the client for a single request to `url`, after checking the url points to a
+/// public web server. The host is pinned to the checked addresses, so it can't resolve to
+/// something else by the time the request is made.
+async fn checked_client(url: &Url) -> Result<Client, ToolError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ToolError::InvalidInput(format!(
+            "Unsupported scheme: {}",
+            url.scheme()
+        )));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| ToolError::InvalidInput(format!("No host in {}", url)))?;
+    if storage_host().as_deref() == Some(host.to_lowercase().as_str()) {
+        return Err(ToolError::InvalidInput(format!("Can't scrape {}", host)));
+    }
+
+    let port = url.port_or_known_default().unwrap_or(80);
+    let literal = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses = match literal.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|err| ToolError::InvalidInput(format!("Can't resolve {}: {}", host, err)))?
+            .collect(),
+    };
+    if addresses.is_empty() || addresses.iter().any(|address| !is_public(address.ip())) {
+        return Err(ToolError::InvalidInput(format!(
+            "{} is not a public address",
+            host
+        )));
+    }
+
+    let mut builder = Client::builder()
+        .redirect(Policy::none())
+        .timeout(REQUEST_TIMEOUT);
+    if url.domain().is_some() {
+        builder = builder.resolve(host, addresses[0]);
+    }
+    Ok(builder.build()?)
+}
+
+/// Fetches `url`, following redirects only as long as every hop passes the same checks.
+async fn fetch(url: &str) -> Result<Response, ToolError> {
+    let mut url = Url::parse(url)
+        .map_err(|err| ToolError::InvalidInput(format!("Invalid url {}: {}", url, err)))?;
+    for _ in 0..=MAX_REDIRECTS {
+        let response = checked_client(&url).await?.get(url.clone()).send().await?;
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .filter(|_| response.status().is_redirection())
+            .map(str::to_owned);
+        match location {
+            Some(location) => {
+                url = url.join(&location).map_err(|err| {
+                    ToolError::InvalidInput(format!("Invalid redirect {}: {}", location, err))
+                })?
+            }
+            None => return Ok(response.error_for_status()?),
+        }
     }
+    Err(ToolError::Process(format!(
+        "More than {} redirects",
+        MAX_REDIRECTS
+    )))
+}
+
+fn to_text(content_type: &str, body: &[u8]) -> Result<String, ToolError> {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+
+    match mime.as_str() {
+        "text/html" | "application/xhtml+xml" | "" => Ok(html2text::from_read(body, TEXT_WIDTH)),
+        "application/json" | "application/xml" => Ok(String::from_utf8_lossy(body).into_owned()),
+        mime if mime.starts_with("text/") => Ok(String::from_utf8_lossy(body).into_owned()),
+        mime => Err(ToolError::InvalidInput(format!(
+            "Unsupported content type: {}",
+            mime
+        ))),
+    }
+}
+
+pub struct WebScrapeTool;
 
+#[async_trait]
+impl AlvariumTool for WebScrapeTool {
+    type Arguments = WebScrapeToolArguments;
     fn name() -> String {
         "web_scrape".to_owned()
     }
 
     fn description() -> String {
-        match Self::definition() {
-            AssistantTools::Function(AssistantToolsFunction { function, .. }) => {
-                function.description.unwrap_or_default()
-            }
-            _ => "".to_owned(),
-        }
+        "Scrape a web page and get its content in plain text".to_owned()
     }
 
     async fn run(
-        _args: Self::Arguments,
-        _context: &Context,
+        args: Self::Arguments,
+        _context: &ToolContext<'_>,
         tool: &RunToolCallObject,
-    ) -> ToolsOutputs {
-        ToolsOutputs {
+    ) -> Result<ToolsOutputs, ToolError> {
+        let mut response = fetch(&args.url).await?;
+        let url = response.url().to_string();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_owned();
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_BYTES {
+                body.truncate(MAX_BYTES);
+                break;
+            }
+        }
+
+        let text = to_text(&content_type, &body)?;
+        let budget = args
+            .max_characters
+            .map_or_else(max_characters, |max| max.min(max_characters()));
+        let (content, truncated) = truncate_chars(text.trim(), budget);
+
+        Ok(ToolsOutputs {
             tool_call_id: Some(tool.id.clone()),
-            output: Some(json!({"error": "Tool not implemented"}).to_string()),
+            output: Some(
+                json!({
+                    "url": url,
+                    "content_type": content_type,
+                    "content": content,
+                    "truncated": truncated,
+                })
+                .to_string(),
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_chars() {
+        assert_eq!(truncate_chars("hello", 10), ("hello".to_string(), false));
+        assert_eq!(truncate_chars("hello", 5), ("hello".to_string(), false));
+        assert_eq!(truncate_chars("hello", 3), ("hel".to_string(), true));
+        assert_eq!(truncate_chars("héllo", 2), ("hé".to_string(), true));
+    }
+
+    #[test]
+    fn test_to_text() {
+        let html = b"<html><body><h1>Title</h1><p>Some text</p></body></html>";
+        let text = to_text("text/html; charset=utf-8", html).unwrap();
+        assert!(text.contains("Title"));
+        assert!(text.contains("Some text"));
+        assert!(!text.contains("<p>"));
+
+        let json = to_text("application/json", b"{\"a\": 1}").unwrap();
+        assert_eq!(json, "{\"a\": 1}");
+
+        assert!(to_text("image/png", &[0x89, 0x50]).is_err());
+    }
+
+    #[test]
+    fn test_is_public() {
+        let public = |ip: &str| is_public(ip.parse().unwrap());
+        assert!(public("93.184.216.34"));
+        assert!(public("2606:2800:220:1:248:1893:25c8:1946"));
+        assert!(!public("127.0.0.1"));
+        assert!(!public("10.1.2.3"));
+        assert!(!public("172.16.0.1"));
+        assert!(!public("192.168.1.1"));
+        assert!(!public("169.254.169.254"));
+        assert!(!public("100.64.0.1"));
+        assert!(!public("0.0.0.0"));
+        assert!(!public("::1"));
+        assert!(!public("::"));
+        assert!(!public("fd00::1"));
+        assert!(!public("fe80::1"));
+        assert!(!public("::ffff:127.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn test_checked_client_rejects_internal_urls() {
+        for url in [
+            "file:///etc/passwd",
+            "ftp://example.com/file",
+            "http://127.0.0.1:9000/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]/",
+            "http://localhost/",
+        ] {
+            let result = checked_client(&Url::parse(url).unwrap()).await;
+            assert!(
+                matches!(result, Err(ToolError::InvalidInput(_))),
+                "{} was not rejected",
+                url
+            );
         }
     }
 }
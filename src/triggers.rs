@@ -0,0 +1,330 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use async_openai::types::AssistantObject;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// A rule that decides which assistants respond to a message in a channel.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    /// The assistant's name appears as a whole word in the message
+    Name,
+    /// The bot is @mentioned, answered by the default assistant
+    Mention,
+    /// The message replies to something the assistant posted through the webhook
+    Reply,
+    /// The message matches `pattern`, answered by `assistant_id`
+    Regex {
+        pattern: String,
+        assistant_id: String,
+    },
+    /// The default assistant answers every message
+    Always,
+}
+
+impl Trigger {
+    /// Name of the trigger as used by the `/triggers` command.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Trigger::Name => "name",
+            Trigger::Mention => "mention",
+            Trigger::Reply => "reply",
+            Trigger::Regex { .. } => "regex",
+            Trigger::Always => "always",
+        }
+    }
+}
+
+pub fn default_triggers() -> Vec<Trigger> {
+    vec![Trigger::Name, Trigger::Mention, Trigger::Reply]
+}
+
+/// The parts of a Discord message the triggers look at.
+#[derive(Debug, Default)]
+pub struct IncomingMessage<'a> {
+    pub content: &'a str,
+    pub mentions_bot: bool,
+    /// Author name of the webhook message this message replies to, if any
+    pub replied_to_assistant: Option<&'a str>,
+}
+
+/// Compiles a case insensitive regex, as used by the regex trigger.
+pub fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+/// The compiled pattern of a regex trigger, `None` when it is invalid. Channel
+/// configurations are read for every message, so patterns are only compiled once.
+fn cached_pattern(pattern: &str) -> Option<Regex> {
+    static PATTERNS: OnceLock<Mutex<HashMap<String, Option<Regex>>>> = OnceLock::new();
+    PATTERNS
+        .get_or_init(Default::default)
+        .lock()
+        .expect("Failed to lock trigger patterns")
+        .entry(pattern.to_string())
+        .or_insert_with(|| compile_pattern(pattern).ok())
+        .clone()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether `name` appears in `content` as a whole word, ignoring case. Like `\b`, a word
+/// character at the edge of the name may not touch another one, but names that start or
+/// end with punctuation, like "C-3PO!" or "R2.", match too.
+fn mentions_name(content: &str, name: &str) -> bool {
+    let content = content.to_lowercase();
+    let name = name.to_lowercase();
+    let (first, last) = match (name.chars().next(), name.chars().next_back()) {
+        (Some(first), Some(last)) => (is_word_char(first), is_word_char(last)),
+        _ => return false,
+    };
+
+    content.char_indices().any(|(start, _)| {
+        if !content[start..].starts_with(&name) {
+            return false;
+        }
+        let before = content[..start].chars().next_back();
+        let after = content[start + name.len()..].chars().next();
+        !(first && before.is_some_and(is_word_char)) && !(last && after.is_some_and(is_word_char))
+    })
+}
+
+/// Extracts the webhook id from a webhook url like
+/// `https://discord.com/api/webhooks/<id>/<token>`.
+pub fn webhook_id(url: &str) -> Option<u64> {
+    url.split("/webhooks/")
+        .nth(1)?
+        .split('/')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Returns the assistants that should respond to `message`, in the order of the
+/// triggers and without duplicates.
+pub fn triggered<'a>(
+    triggers: &[Trigger],
+    message: &IncomingMessage<'_>,
+    assistants: &[&'a AssistantObject],
+    default_assistant: Option<&str>,
+) -> Vec<&'a AssistantObject> {
+    let by_id = |id: &str| {
+        assistants
+            .iter()
+            .copied()
+            .find(|assistant| assistant.id == id)
+    };
+    let default = default_assistant.and_then(by_id);
+
+    let mut responding: Vec<&AssistantObject> = vec![];
+    for trigger in triggers {
+        let matched: Vec<&AssistantObject> = match trigger {
+            Trigger::Name => assistants
+                .iter()
+                .copied()
+                .filter(|assistant| {
+                    assistant
+                        .name
+                        .as_ref()
+                        .is_some_and(|name| mentions_name(message.content, name))
+                })
+                .collect(),
+            Trigger::Mention if message.mentions_bot => default.into_iter().collect(),
+            Trigger::Reply => match message.replied_to_assistant {
+                Some(author) => assistants
+                    .iter()
+                    .copied()
+                    .filter(|assistant| assistant.name.as_deref() == Some(author))
+                    .collect(),
+                None => vec![],
+            },
+            Trigger::Regex {
+                pattern,
+                assistant_id,
+            } => match cached_pattern(pattern) {
+                Some(regex) if regex.is_match(message.content) => {
+                    by_id(assistant_id).into_iter().collect()
+                }
+                _ => vec![],
+            },
+            Trigger::Always => default.into_iter().collect(),
+            _ => vec![],
+        };
+
+        for assistant in matched {
+            if !responding.iter().any(|other| other.id == assistant.id) {
+                responding.push(assistant);
+            }
+        }
+    }
+    responding
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assistant;
+
+    fn ids(assistants: Vec<&AssistantObject>) -> Vec<&str> {
+        assistants
+            .into_iter()
+            .map(|assistant| assistant.id.as_str())
+            .collect()
+    }
+
+    fn message(content: &str) -> IncomingMessage<'_> {
+        IncomingMessage {
+            content,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_name_matches_whole_words() {
+        let ada = assistant("asst_ada", "Ada");
+        let assistants = vec![&ada];
+        let triggers = vec![Trigger::Name];
+
+        let matched = |content| ids(triggered(&triggers, &message(content), &assistants, None));
+        assert_eq!(matched("hey ada, how are you?"), vec!["asst_ada"]);
+        assert_eq!(matched("ADA!"), vec!["asst_ada"]);
+        assert!(matched("I live in Canada").is_empty());
+        assert!(matched("adapter").is_empty());
+    }
+
+    #[test]
+    fn test_name_with_punctuation_edges() {
+        let droid = assistant("asst_droid", "C-3PO!");
+        let r2 = assistant("asst_r2", "R2.");
+        let assistants = vec![&droid, &r2];
+        let triggers = vec![Trigger::Name];
+
+        let matched = |content| ids(triggered(&triggers, &message(content), &assistants, None));
+        assert_eq!(matched("hello c-3po! how are you"), vec!["asst_droid"]);
+        assert_eq!(matched("C-3PO!?"), vec!["asst_droid"]);
+        assert_eq!(matched("beep R2. beep"), vec!["asst_r2"]);
+        assert_eq!(matched("(r2.)"), vec!["asst_r2"]);
+        assert!(matched("XC-3PO!").is_empty());
+        assert!(matched("AR2.").is_empty());
+        assert!(matched("R2").is_empty());
+    }
+
+    #[test]
+    fn test_mention_and_always_use_default() {
+        let ada = assistant("asst_ada", "Ada");
+        let bob = assistant("asst_bob", "Bob");
+        let assistants = vec![&ada, &bob];
+
+        let mention = IncomingMessage {
+            content: "<@1> hi",
+            mentions_bot: true,
+            ..Default::default()
+        };
+        let triggers = vec![Trigger::Mention];
+        assert_eq!(
+            ids(triggered(
+                &triggers,
+                &mention,
+                &assistants,
+                Some("asst_bob")
+            )),
+            vec!["asst_bob"]
+        );
+        assert!(triggered(&triggers, &mention, &assistants, None).is_empty());
+        assert!(triggered(&triggers, &message("hi"), &assistants, Some("asst_bob")).is_empty());
+
+        let triggers = vec![Trigger::Always];
+        assert_eq!(
+            ids(triggered(
+                &triggers,
+                &message("hi"),
+                &assistants,
+                Some("asst_ada")
+            )),
+            vec!["asst_ada"]
+        );
+    }
+
+    #[test]
+    fn test_reply_to_assistant() {
+        let ada = assistant("asst_ada", "Ada");
+        let bob = assistant("asst_bob", "Bob");
+        let assistants = vec![&ada, &bob];
+        let reply = IncomingMessage {
+            content: "thanks",
+            replied_to_assistant: Some("Bob"),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            ids(triggered(&[Trigger::Reply], &reply, &assistants, None)),
+            vec!["asst_bob"]
+        );
+        assert!(triggered(&[Trigger::Name], &reply, &assistants, None).is_empty());
+    }
+
+    #[test]
+    fn test_regex_and_deduplication() {
+        let ada = assistant("asst_ada", "Ada");
+        let assistants = vec![&ada];
+        let triggers = vec![
+            Trigger::Name,
+            Trigger::Regex {
+                pattern: r"^(deploy|release)\b".to_string(),
+                assistant_id: "asst_ada".to_string(),
+            },
+        ];
+
+        let matched = |content| ids(triggered(&triggers, &message(content), &assistants, None));
+        assert_eq!(matched("Deploy the app"), vec!["asst_ada"]);
+        assert_eq!(matched("release it, Ada"), vec!["asst_ada"]);
+        assert!(matched("please deploy").is_empty());
+    }
+
+    #[test]
+    fn test_invalid_regex_never_matches() {
+        let ada = assistant("asst_ada", "Ada");
+        let triggers = vec![Trigger::Regex {
+            pattern: "(".to_string(),
+            assistant_id: "asst_ada".to_string(),
+        }];
+        assert!(triggered(&triggers, &message("("), &[&ada], None).is_empty());
+    }
+
+    #[test]
+    fn test_webhook_id() {
+        assert_eq!(
+            webhook_id("https://discord.com/api/webhooks/1234/token"),
+            Some(1234)
+        );
+        assert_eq!(
+            webhook_id("https://discord.com/api/v10/webhooks/42/abc"),
+            Some(42)
+        );
+        assert_eq!(webhook_id("not a webhook"), None);
+    }
+
+    #[test]
+    fn test_trigger_serialization() {
+        let triggers: Vec<Trigger> = serde_json::from_str(
+            r#"[{"type":"name"},{"type":"regex","pattern":"hi","assistant_id":"asst_ada"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            triggers,
+            vec![
+                Trigger::Name,
+                Trigger::Regex {
+                    pattern: "hi".to_string(),
+                    assistant_id: "asst_ada".to_string()
+                }
+            ]
+        );
+    }
+}